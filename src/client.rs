@@ -9,15 +9,17 @@ use conrod_core::text::Font;
 
 use cgmath::{Point3, Vector2, Vector3};
 
-use nd_iter::iter_3d;
-
 use rand::prelude::*;
 use rand_xorshift::XorShiftRng;
 
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
+use std::hash::Hasher;
 use std::time::{Duration, SystemTime};
 
+use material::{Material, MaterialProps};
+use options::Options;
 use render::{VoxInd, VoxelVertex};
+use world::{Grid, StateHash, VoxPos};
 use {input, physics, render};
 
 pub struct Ui {
@@ -48,6 +50,7 @@ pub struct Player {
     pub angle: Vector2<f32>,
     pub velocity: Vector3<f32>,
     pub state: PlayerState,
+    pub knocked_back: bool, // Whether a blast threw the player, who can't steer until landing
 }
 
 // A block directly in the player's line of sight
@@ -57,14 +60,36 @@ pub struct SightBlock {
     pub new_pos: Point3<VoxInd>, // Position of new block created from right-clicking
 }
 
-#[derive(Clone, Copy)]
-pub enum Voxel {
-    Air,
-    Boundary,
-    Sand(VoxelShade),
+// Electric state of a voxel. A pulse travels through conductors as a charged voxel followed by a
+// discharging one, which keeps the pulse from flowing backwards.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Charge {
+    Neutral,
+    Charged,
+    Discharging,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Voxel {
+    pub material: Material,
+    pub shade: VoxelShade, // Small color variation between voxels of the same material
+    pub burning: bool,     // Whether the voxel has caught fire
+    pub charge: Charge,    // Electric pulse passing through the voxel
+}
+
+// A voxel flying through the air, such as one thrown by a blast, which lands back in the voxel
+// grid when it hits something
+#[derive(Clone, Copy, Debug)]
+pub struct Particle {
+    pub voxel: Voxel,
+    pub pos: Point3<f32>,
+    pub velocity: Vector3<f32>, // In voxels per tick
 }
 
-pub type VoxelGrid = Box<[[[Voxel; VOX_MAX_Z]; VOX_MAX_Y]; VOX_MAX_X]>;
+pub type VoxelGrid = Grid<Voxel>;
+pub type TempGrid = Grid<f32>; // Temperature of each voxel, in degrees Celsius
+pub type PressureGrid = Grid<f32>; // Pressure on each voxel, in kg/m^2 of material resting on it
+pub type AwakeSet = BTreeSet<VoxPos>; // Chunks of the voxel grid that are simulated
 pub type VoxelShade = u8;
 
 pub struct GameTimers {
@@ -76,16 +101,28 @@ pub struct GameTimers {
 pub struct GameState {
     pub running: bool,
     pub paused: bool,
-    pub frame: u32,
+    pub sand_paused: bool, // Whether the voxel simulation is frozen while the player moves on
+    pub step: u64,         // Number of fixed physics steps run so far
+    pub tick: u64,         // Number of voxel simulation ticks run so far
+    pub tick_progress: u32, // Progress towards the next tick, in `physics::SIM_SPEEDS` units
+    pub pending_ticks: u32, // Ticks requested by single-stepping while the sand is paused
+    pub sim_speed: usize,  // Index of the voxel simulation speed in `physics::SIM_SPEEDS`
+    pub unsimulated: f32,  // Time the physics hasn't caught up with yet, in seconds
     pub player: Player,
     pub sight_block: Option<SightBlock>,
     pub voxels: VoxelGrid,
+    pub temps: TempGrid,
+    pub awake: AwakeSet, // Chunks with voxels that might change next tick
+    pub loaded_around: Option<VoxPos>, // Chunk the loaded chunks are centered on, once loaded
+    pub particles: Vec<Particle>,
     pub voxels_mesh: Vec<VoxelVertex>,
     pub dirty: bool,
     pub keys_down: HashMap<VirtualKeyCode, bool>,
     pub mouse_btns_down: HashMap<MouseButton, bool>,
-    pub rng: XorShiftRng,
+    pub seed: u64,        // Seed that `rng` started from
+    pub rng: XorShiftRng, // Source of all the game's randomness, so a seed always plays out the same
     pub timers: GameTimers,
+    pub brush: Material, // Material placed when right-clicking
 }
 
 pub struct Client {
@@ -94,13 +131,10 @@ pub struct Client {
     pub state: GameState,
 }
 
-pub const VOX_MAX_X: usize = 50;
-pub const VOX_MAX_Y: usize = 50;
-pub const VOX_MAX_Z: usize = 50;
-
 const GAME_NAME: &str = "Sandvox";
 const WIN_W: u32 = 800;
 const WIN_H: u32 = 600;
+const MAX_FRAME_TIME: f32 = 0.25; // Most time the physics catches up on in one frame, in seconds
 const INIT_POS: Point3<f32> = Point3 {
     x: 0.0,
     y: 1.5, // TODO: Each voxel is 1 cm and the camera is 1.5 m above ground
@@ -176,59 +210,117 @@ impl GameTimers {
 }
 
 impl Voxel {
-    pub fn is_air(&self) -> bool {
-        match *self {
-            Voxel::Air => true,
-            _ => false,
+    pub const AIR: Voxel = Voxel {
+        material: Material::Air,
+        shade: 0,
+        burning: false,
+        charge: Charge::Neutral,
+    };
+    pub const BOUNDARY: Voxel = Voxel {
+        material: Material::Boundary,
+        shade: 0,
+        burning: false,
+        charge: Charge::Neutral,
+    };
+
+    pub fn new(material: Material, shade: VoxelShade) -> Self {
+        Voxel {
+            material,
+            shade,
+            burning: false,
+            charge: Charge::Neutral,
         }
     }
+
+    pub fn is_air(&self) -> bool {
+        self.material == Material::Air
+    }
+
+    // Determine if the voxel is on fire. Flames are always on fire.
+    pub fn is_burning(&self) -> bool {
+        self.burning || self.material == Material::Fire
+    }
+
+    // Look up the properties of the voxel's material
+    pub fn props(&self) -> &'static MaterialProps {
+        self.material.props()
+    }
+}
+
+impl StateHash for Voxel {
+    fn state_hash<H: Hasher>(&self, hasher: &mut H) {
+        let charge = match self.charge {
+            Charge::Neutral => 0,
+            Charge::Charged => 1,
+            Charge::Discharging => 2,
+        };
+        hasher.write(&[self.material.id(), self.shade, self.burning as u8, charge]);
+    }
 }
 
 impl GameState {
     // Initialize the game state object
-    fn init() -> Self {
-        let mut rng = SeedableRng::seed_from_u64(0);
+    fn init(options: &Options) -> Self {
+        let mut rng = SeedableRng::seed_from_u64(options.seed);
+        let voxels = make_test_world(&mut rng, options.world_size);
         GameState {
             running: true,
             paused: true,
-            frame: 0,
+            sand_paused: false,
+            step: 0,
+            tick: 0,
+            tick_progress: 0,
+            pending_ticks: 0,
+            sim_speed: physics::NORMAL_SPEED,
+            unsimulated: 0.0,
             player: Player {
                 pos: INIT_POS,
                 angle: Vector2::new(0.0, 0.0),
                 velocity: Vector3::new(0.0, 0.0, 0.0),
                 state: PlayerState::Normal,
+                knocked_back: false,
             },
             sight_block: None,
-            voxels: make_test_world(&mut rng),
+            temps: physics::make_temps(&voxels),
+            awake: physics::make_awake(&voxels),
+            loaded_around: None,
+            voxels,
+            particles: Vec::new(),
             voxels_mesh: Vec::new(),
             dirty: true,
             keys_down: HashMap::new(),
             mouse_btns_down: HashMap::new(),
+            seed: options.seed,
             rng,
             timers: GameTimers::init(),
+            brush: Material::Sand,
         }
     }
 }
 
 impl Client {
     // Initialize the game client (event loop, window creation, OpenGL, game state)
-    pub fn init() -> Self {
+    pub fn init(options: &Options) -> Self {
         let evs = EventsLoop::new();
         let gfx = Graphics::init(&evs);
-        let state = GameState::init();
+        let state = GameState::init(options);
         Client { evs, gfx, state }
     }
 }
 
+// Create a world of `size` voxels along each axis, containing only air
+pub fn make_empty_world(size: VoxPos) -> VoxelGrid {
+    Grid::new(size, Voxel::AIR)
+}
+
 // Create an initial diagonal stripe test world
 // TODO: Remove this
-fn make_test_world<R: Rng>(rng: &mut R) -> VoxelGrid {
-    let mut voxels = Box::new([[[Voxel::Air; VOX_MAX_Z]; VOX_MAX_Y]; VOX_MAX_X]);
-    for (x, y, z) in iter_3d(0..VOX_MAX_X, 0..VOX_MAX_Y, 0..VOX_MAX_Z) {
-        if x == y && y == z {
-            // TODO: Use random instead of coord cast
-            voxels[x][y][z] = Voxel::Sand(rng.gen());
-        }
+fn make_test_world<R: Rng>(rng: &mut R, size: VoxPos) -> VoxelGrid {
+    let mut voxels = make_empty_world(size);
+    let (sx, sy, sz) = size;
+    for i in 0..sx.min(sy).min(sz) {
+        // TODO: Use random instead of coord cast
+        voxels[(i, i, i)] = Voxel::new(Material::Sand, rng.gen());
     }
     voxels
 }
@@ -249,15 +341,27 @@ fn do_paused(client: &mut Client) {
     }
 }
 
-// Update the game state for the current frame
+// Run as many fixed physics steps as fit in the time that has passed, carrying the remainder over
+// to the next frame. This keeps the physics running at the same rate whatever the frame rate.
+fn do_physics(state: &mut GameState, dt: f32) {
+    state.unsimulated = (state.unsimulated + dt).min(MAX_FRAME_TIME);
+    while state.unsimulated >= physics::STEP_TIME {
+        physics::do_player_physics(&mut state.player, state.voxels.size(), physics::STEP_TIME);
+        physics::load_around_player(state);
+        physics::do_sandfall(state);
+        state.step += 1;
+        state.unsimulated -= physics::STEP_TIME;
+    }
+}
+
+// Update the game state for the current frame, taking `dt` seconds since the last frame
 // NB: This isn't the only place where the game state is modified
 pub fn update(client: &mut Client, dt: f32) {
     if client.state.paused {
         do_paused(client);
     } else {
         input::do_keys_down(client);
-        physics::do_player_physics(&mut client.state.player, dt);
-        physics::do_sandfall(&mut client.state);
+        do_physics(&mut client.state, dt);
         client.state.sight_block = render::get_sight_block(&client.state);
     }
 }
@@ -267,3 +371,29 @@ pub fn get_time_delta(prev_time: &SystemTime) -> f32 {
     let elapsed = prev_time.elapsed().unwrap_or_else(|_| Duration::new(0, 0));
     elapsed.as_secs() as f32 + elapsed.subsec_millis() as f32 / 1000.0
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use world::DEFAULT_WORLD_SIZE;
+
+    #[test]
+    fn physics_catches_up_in_fixed_steps() {
+        let mut state = GameState::init(&Options {
+            world_size: DEFAULT_WORLD_SIZE,
+            seed: 0,
+        });
+        // A long frame only catches up on `MAX_FRAME_TIME`
+        do_physics(&mut state, 0.3);
+        assert_eq!(
+            state.step,
+            (MAX_FRAME_TIME / physics::STEP_TIME).round() as u64
+        );
+        // Time left over from a short frame is carried over to the next one
+        let step = state.step;
+        do_physics(&mut state, 0.01);
+        assert_eq!(state.step, step);
+        do_physics(&mut state, 0.01);
+        assert_eq!(state.step, step + 1);
+    }
+}
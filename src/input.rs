@@ -1,5 +1,6 @@
 use glium::glutin::{
-    DeviceEvent, ElementState, Event, KeyboardInput, MouseButton, VirtualKeyCode, WindowEvent,
+    DeviceEvent, ElementState, Event, KeyboardInput, MouseButton, MouseScrollDelta, VirtualKeyCode,
+    WindowEvent,
 };
 
 use cgmath::prelude::*;
@@ -13,19 +14,69 @@ use std::f32::consts::PI;
 use std::time::SystemTime;
 
 use client::{Client, GameState, Graphics, PlayerState, SightBlock, Voxel};
+use material::Material;
 use {client, physics};
 
 const TURN_SPEED: f32 = 0.01;
 const DOUBLE_PRESS_THRESH: f32 = 0.3; // TODO: Is this a good value?
 
+// Materials the player can place, cycled through with the mouse wheel. The first ten can also be
+// selected with the number keys.
+const BRUSH_MATERIALS: [Material; 22] = [
+    Material::Sand,
+    Material::Water,
+    Material::Oil,
+    Material::Steam,
+    Material::Wood,
+    Material::Dust,
+    Material::Fire,
+    Material::Lava,
+    Material::Stone,
+    Material::Acid,
+    Material::Wall,
+    Material::Ice,
+    Material::Freezer,
+    Material::Tnt,
+    Material::Spring,
+    Material::Spout,
+    Material::Drain,
+    Material::Seed,
+    Material::Soil,
+    Material::Wire,
+    Material::Metal,
+    Material::Battery,
+];
+
 fn handle_mouse_input(state: &mut GameState, mouse_state: ElementState, btn: MouseButton) {
     let down = mouse_state == ElementState::Pressed;
     state.mouse_btns_down.insert(btn, down);
 }
 
+// Select the next or previous brush material, depending on the scroll direction
+fn handle_mouse_wheel(state: &mut GameState, delta: MouseScrollDelta) {
+    let dy = match delta {
+        MouseScrollDelta::LineDelta(_, dy) => dy,
+        MouseScrollDelta::PixelDelta(pos) => pos.y as f32,
+    };
+    let len = BRUSH_MATERIALS.len();
+    let i = BRUSH_MATERIALS
+        .iter()
+        .position(|&material| material == state.brush)
+        .unwrap_or(0);
+    let step = if dy > 0.0 {
+        1
+    } else if dy < 0.0 {
+        len - 1
+    } else {
+        0
+    };
+    state.brush = BRUSH_MATERIALS[(i + step) % len];
+}
+
 fn handle_window_event(ev: &WindowEvent, state: &mut GameState) {
     match ev {
         WindowEvent::CloseRequested => state.running = false,
+        WindowEvent::MouseWheel { delta, .. } => handle_mouse_wheel(state, *delta),
         WindowEvent::MouseInput {
             state: mouse_state,
             button,
@@ -49,13 +100,42 @@ fn do_press_forward(state: &mut GameState) {
     }
 }
 
+// Get the index in `BRUSH_MATERIALS` selected by a number key
+fn get_brush_index(key: VirtualKeyCode) -> Option<usize> {
+    match key {
+        VirtualKeyCode::Key1 => Some(0),
+        VirtualKeyCode::Key2 => Some(1),
+        VirtualKeyCode::Key3 => Some(2),
+        VirtualKeyCode::Key4 => Some(3),
+        VirtualKeyCode::Key5 => Some(4),
+        VirtualKeyCode::Key6 => Some(5),
+        VirtualKeyCode::Key7 => Some(6),
+        VirtualKeyCode::Key8 => Some(7),
+        VirtualKeyCode::Key9 => Some(8),
+        VirtualKeyCode::Key0 => Some(9),
+        _ => None,
+    }
+}
+
+// Select the material placed when right-clicking
+fn select_brush(key: VirtualKeyCode, state: &mut GameState) {
+    if let Some(&material) = get_brush_index(key).and_then(|i| BRUSH_MATERIALS.get(i)) {
+        state.brush = material;
+    }
+}
+
 // Change game state based on a keypress. This is needed because `do_keys_down()` only knows if a
 // key is currently down.
 fn do_key_press(key: VirtualKeyCode, state: &mut GameState) {
     match key {
         VirtualKeyCode::Tab => physics::toggle_flight(state),
         VirtualKeyCode::W => do_press_forward(state),
-        _ => {}
+        VirtualKeyCode::P => physics::toggle_sand_pause(state),
+        VirtualKeyCode::Period => physics::step_sand(state),
+        VirtualKeyCode::LBracket => physics::change_sim_speed(state, false),
+        VirtualKeyCode::RBracket => physics::change_sim_speed(state, true),
+        VirtualKeyCode::H => physics::print_world_hash(state),
+        _ => select_brush(key, state),
     }
 }
 
@@ -145,32 +225,42 @@ pub fn do_keys_down(client: &mut Client) {
     let right = right.normalize();
     let (lateral_move_speed, up_move_speed) = physics::get_move_speeds(client.state.player.state);
 
-    // TODO: Make this clearer
-    client.state.player.velocity.x = 0.0;
-    client.state.player.velocity.z = 0.0;
-    if !physics::player_in_freefall(&client.state) {
-        // Jump/fly up
-        client.state.player.velocity.y = if key_down(&client.state, VirtualKeyCode::Space) {
-            up_move_speed
-        } else {
-            0.0
-        }
-    }
-    // Move forward
-    if key_down(&client.state, VirtualKeyCode::W) {
-        client.state.player.velocity += forward * lateral_move_speed
-    }
-    // Move backward
-    if key_down(&client.state, VirtualKeyCode::R) {
-        client.state.player.velocity -= forward * lateral_move_speed
-    }
-    // Move left
-    if key_down(&client.state, VirtualKeyCode::A) {
-        client.state.player.velocity -= right * lateral_move_speed
+    // The player can't steer after being knocked back by a blast until they land again, so that
+    // they keep the momentum it gave them
+    if client.state.player.knocked_back
+        && !physics::player_in_freefall(&client.state)
+        && client.state.player.velocity.y <= 0.0
+    {
+        client.state.player.knocked_back = false;
     }
-    // Move right
-    if key_down(&client.state, VirtualKeyCode::S) {
-        client.state.player.velocity += right * lateral_move_speed
+    if !client.state.player.knocked_back {
+        // TODO: Make this clearer
+        client.state.player.velocity.x = 0.0;
+        client.state.player.velocity.z = 0.0;
+        if !physics::player_in_freefall(&client.state) {
+            // Jump/fly up
+            client.state.player.velocity.y = if key_down(&client.state, VirtualKeyCode::Space) {
+                up_move_speed
+            } else {
+                0.0
+            }
+        }
+        // Move forward
+        if key_down(&client.state, VirtualKeyCode::W) {
+            client.state.player.velocity += forward * lateral_move_speed
+        }
+        // Move backward
+        if key_down(&client.state, VirtualKeyCode::R) {
+            client.state.player.velocity -= forward * lateral_move_speed
+        }
+        // Move left
+        if key_down(&client.state, VirtualKeyCode::A) {
+            client.state.player.velocity -= right * lateral_move_speed
+        }
+        // Move right
+        if key_down(&client.state, VirtualKeyCode::S) {
+            client.state.player.velocity += right * lateral_move_speed
+        }
     }
     // Move down
     if key_down(&client.state, VirtualKeyCode::LShift)
@@ -184,18 +274,19 @@ pub fn do_keys_down(client: &mut Client) {
         client::set_pause(&mut client.state, &client.gfx.display, true);
     }
 
-    // Destroy sand
+    // Destroy voxel
     if mouse_btn_down(&client.state, MouseButton::Left) {
         if let Some(SightBlock { pos, .. }) = client.state.sight_block {
-            physics::put_voxel(&mut client.state, pos, Voxel::Air);
+            physics::put_voxel(&mut client.state, pos, Voxel::AIR);
         }
     }
 
-    // Create sand
+    // Create voxel
     if mouse_btn_down(&client.state, MouseButton::Right) {
         if let Some(SightBlock { new_pos, .. }) = client.state.sight_block {
             let shade = client.state.rng.gen();
-            physics::put_voxel(&mut client.state, new_pos, Voxel::Sand(shade));
+            let voxel = Voxel::new(client.state.brush, shade);
+            physics::put_voxel(&mut client.state, new_pos, voxel);
         }
     }
 }
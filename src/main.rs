@@ -10,18 +10,28 @@ extern crate image;
 extern crate nd_iter;
 extern crate rand;
 extern crate rand_xorshift;
+extern crate rayon;
 
+use std::env;
+use std::process;
 use std::time::SystemTime;
 
 mod client;
 mod input;
+mod material;
+mod options;
 mod physics;
 mod render;
+mod world;
 
 use client::Client;
 
 fn main() {
-    let mut client = Client::init();
+    let options = options::parse_args(env::args().skip(1)).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    });
+    let mut client = Client::init(&options);
 
     // Time of the previous frame
     let mut prev_time = SystemTime::now();
@@ -30,8 +40,8 @@ fn main() {
         let dt = client::get_time_delta(&prev_time);
         prev_time = SystemTime::now();
         input::do_input(&mut client);
+        // Simulate the time since the previous frame in fixed steps, then draw the result
         client::update(&mut client, dt);
         render::render(&mut client.gfx, &mut client.state);
-        client.state.frame += 1;
     }
 }
@@ -0,0 +1,508 @@
+// Physical state of a material, which determines how its voxels move
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum MaterialState {
+    Solid,  // Stays in place
+    Powder, // Falls and piles up
+    Liquid, // Falls and flows sideways
+    Gas,    // Floats
+}
+
+// The properties of a material, shared by all voxels of that material
+pub struct MaterialProps {
+    #[cfg_attr(not(test), allow(dead_code))] // Only checked against the registry index by tests
+    pub material: Material,
+    #[cfg_attr(not(test), allow(dead_code))] // Not shown in the UI yet
+    pub name: &'static str,
+    pub state: MaterialState,
+    // In kg/m^3
+    pub density: f32,
+    // Base color, before shade variation is added
+    pub color: [f32; 4],
+    // Chance per tick of igniting next to fire, from 0.0 to 1.0
+    pub flammability: f32,
+    // Average number of ticks the material burns for
+    pub burn_time: f32,
+    // What the material turns into once it burns out
+    pub burns_into: Material,
+    // Chance per tick of a fluid holding still instead of moving, from 0.0 to 1.0
+    pub viscosity: f32,
+    // Average number of ticks a gas lasts before dissipating, or 0.0 to last forever
+    pub lifetime: f32,
+    // Chance per tick of a falling (or rising) voxel drifting sideways instead, from 0.0 to 1.0
+    pub dispersion: f32,
+    // Temperature of newly created voxels, in degrees Celsius
+    pub temperature: f32,
+    // How readily heat flows into and out of the material, from 0.0 to 1.0
+    pub conductivity: f32,
+    // Heat needed to warm the material by one degree, at least 1.0
+    pub heat_capacity: f32,
+    // Whether the material stays at its initial temperature, heating or cooling its neighbors
+    pub holds_temperature: bool,
+    // Melting or boiling point, and what the material turns into above it
+    pub heats_into: Option<(f32, Material)>,
+    // Freezing or condensation point, and what the material turns into below it
+    pub cools_into: Option<(f32, Material)>,
+    // Temperature above which the material catches fire
+    pub ignition_point: Option<f32>,
+    // Pressure above which a powder compacts, in kg/m^2, and what it compacts into
+    pub compacts_into: Option<(f32, Material)>,
+    // Chance per tick of not being dissolved by touching acid, from 0.0 to 1.0
+    pub acid_resistance: f32,
+    // Whether a solid falls when there is nothing under it to hold it up
+    pub crumbles: bool,
+    // How much a blast weakens passing through the material, in voxels of blast radius
+    pub hardness: f32,
+    // Radius of the blast when the material explodes, in voxels, or 0.0 if it doesn't explode
+    pub blast_radius: f32,
+    // How often the material produces a voxel next to it, in ticks, and what it produces
+    pub emits: Option<(u64, Material)>,
+    // Whether the material swallows the fluids and powders that flow into it
+    pub drains: bool,
+    // Whether seeds can grow on the material when it's wet
+    pub fertile: bool,
+    // Whether electric pulses travel through the material
+    pub conductive: bool,
+    // Whether the material charges the conductors touching it, like a battery
+    pub charges: bool,
+}
+
+// Temperature the world starts at, in degrees Celsius
+pub const ROOM_TEMP: f32 = 20.0;
+
+// A material, identified by its index in the material registry
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum Material {
+    Air,
+    Boundary,
+    Sand,
+    Water,
+    Oil,
+    Steam,
+    Fire,
+    Smoke,
+    Wood,
+    Dust,
+    Lava,
+    Stone,
+    Glass,
+    Ice,
+    Acid,
+    Wall,
+    Freezer,
+    Sandstone,
+    Tnt,
+    Spring,
+    Spout,
+    Drain,
+    Seed,
+    Plant,
+    Soil,
+    Wire,
+    Metal,
+    Battery,
+}
+
+// Properties shared by most materials, which registry entries only need to override
+const DEFAULT_PROPS: MaterialProps = MaterialProps {
+    material: Material::Air,
+    name: "",
+    state: MaterialState::Solid,
+    density: 1000.0,
+    color: [1.0, 1.0, 1.0, 1.0],
+    flammability: 0.0,
+    burn_time: 0.0,
+    burns_into: Material::Air,
+    viscosity: 0.0,
+    lifetime: 0.0,
+    dispersion: 0.0,
+    temperature: ROOM_TEMP,
+    conductivity: 0.1,
+    heat_capacity: 1.0,
+    holds_temperature: false,
+    heats_into: None,
+    cools_into: None,
+    ignition_point: None,
+    compacts_into: None,
+    acid_resistance: 0.0,
+    crumbles: false,
+    hardness: 1.0,
+    blast_radius: 0.0,
+    emits: None,
+    drains: false,
+    fertile: false,
+    conductive: false,
+    charges: false,
+};
+
+// The material registry, indexed by material id. To add a material, add a variant to `Material`
+// and its properties here.
+const MATERIALS: [MaterialProps; 28] = [
+    MaterialProps {
+        material: Material::Air,
+        name: "Air",
+        state: MaterialState::Gas,
+        density: 1.2,
+        color: [1.0, 1.0, 1.0, 0.0],
+        conductivity: 0.01,
+        acid_resistance: 1.0,
+        hardness: 0.0,
+        ..DEFAULT_PROPS
+    },
+    MaterialProps {
+        material: Material::Boundary,
+        name: "Boundary",
+        density: f32::INFINITY,
+        color: [0.0, 0.0, 0.0, 1.0],
+        conductivity: 0.0,
+        acid_resistance: 1.0,
+        hardness: f32::INFINITY,
+        ..DEFAULT_PROPS
+    },
+    MaterialProps {
+        material: Material::Sand,
+        name: "Sand",
+        state: MaterialState::Powder,
+        density: 1600.0,
+        color: [0.93, 0.79, 0.49, 1.0],
+        conductivity: 0.2,
+        heat_capacity: 2.0,
+        heats_into: Some((1700.0, Material::Glass)),
+        compacts_into: Some((20000.0, Material::Sandstone)),
+        hardness: 0.5,
+        fertile: true,
+        ..DEFAULT_PROPS
+    },
+    MaterialProps {
+        material: Material::Water,
+        name: "Water",
+        state: MaterialState::Liquid,
+        density: 1000.0,
+        color: [0.2, 0.4, 0.9, 1.0],
+        conductivity: 0.5,
+        heat_capacity: 4.0,
+        heats_into: Some((100.0, Material::Steam)),
+        cools_into: Some((0.0, Material::Ice)),
+        acid_resistance: 1.0,
+        hardness: 0.5,
+        conductive: true,
+        ..DEFAULT_PROPS
+    },
+    MaterialProps {
+        material: Material::Oil,
+        name: "Oil",
+        state: MaterialState::Liquid,
+        density: 900.0,
+        color: [0.25, 0.2, 0.1, 1.0],
+        flammability: 0.8,
+        burn_time: 20.0,
+        burns_into: Material::Fire,
+        viscosity: 0.2,
+        conductivity: 0.2,
+        heat_capacity: 2.0,
+        ignition_point: Some(250.0),
+        hardness: 0.5,
+        ..DEFAULT_PROPS
+    },
+    MaterialProps {
+        material: Material::Steam,
+        name: "Steam",
+        state: MaterialState::Gas,
+        density: 0.6,
+        color: [0.9, 0.9, 0.9, 1.0],
+        lifetime: 200.0,
+        dispersion: 0.3,
+        temperature: 110.0,
+        conductivity: 0.05,
+        cools_into: Some((100.0, Material::Water)),
+        acid_resistance: 1.0,
+        hardness: 0.0,
+        ..DEFAULT_PROPS
+    },
+    MaterialProps {
+        material: Material::Fire,
+        name: "Fire",
+        state: MaterialState::Gas,
+        density: 0.3,
+        color: [1.0, 0.45, 0.1, 1.0],
+        burn_time: 6.0,
+        burns_into: Material::Smoke,
+        dispersion: 0.3,
+        temperature: 800.0,
+        conductivity: 0.3,
+        acid_resistance: 1.0,
+        hardness: 0.0,
+        ..DEFAULT_PROPS
+    },
+    MaterialProps {
+        material: Material::Smoke,
+        name: "Smoke",
+        state: MaterialState::Gas,
+        density: 0.9,
+        color: [0.3, 0.3, 0.3, 1.0],
+        lifetime: 150.0,
+        dispersion: 0.3,
+        conductivity: 0.05,
+        acid_resistance: 1.0,
+        hardness: 0.0,
+        ..DEFAULT_PROPS
+    },
+    MaterialProps {
+        material: Material::Wood,
+        name: "Wood",
+        density: 700.0,
+        color: [0.45, 0.3, 0.15, 1.0],
+        flammability: 0.3,
+        burn_time: 60.0,
+        burns_into: Material::Fire,
+        heat_capacity: 2.0,
+        ignition_point: Some(300.0),
+        acid_resistance: 0.6,
+        hardness: 2.0,
+        ..DEFAULT_PROPS
+    },
+    MaterialProps {
+        material: Material::Dust,
+        name: "Dust",
+        state: MaterialState::Powder,
+        density: 500.0,
+        color: [0.6, 0.55, 0.5, 1.0],
+        flammability: 0.9,
+        burn_time: 3.0,
+        burns_into: Material::Fire,
+        dispersion: 0.5,
+        ignition_point: Some(200.0),
+        hardness: 0.5,
+        ..DEFAULT_PROPS
+    },
+    MaterialProps {
+        material: Material::Lava,
+        name: "Lava",
+        state: MaterialState::Liquid,
+        density: 3100.0,
+        color: [1.0, 0.3, 0.0, 1.0],
+        viscosity: 0.8,
+        temperature: 1200.0,
+        conductivity: 0.4,
+        heat_capacity: 20.0,
+        cools_into: Some((700.0, Material::Stone)),
+        acid_resistance: 0.5,
+        hardness: 0.5,
+        ..DEFAULT_PROPS
+    },
+    MaterialProps {
+        material: Material::Stone,
+        name: "Stone",
+        density: 2600.0,
+        color: [0.45, 0.45, 0.45, 1.0],
+        conductivity: 0.4,
+        heat_capacity: 2.0,
+        heats_into: Some((1200.0, Material::Lava)),
+        acid_resistance: 0.9,
+        crumbles: true,
+        hardness: 4.0,
+        ..DEFAULT_PROPS
+    },
+    MaterialProps {
+        material: Material::Glass,
+        name: "Glass",
+        density: 2500.0,
+        color: [0.75, 0.9, 0.95, 1.0],
+        conductivity: 0.3,
+        heat_capacity: 2.0,
+        acid_resistance: 1.0,
+        ..DEFAULT_PROPS
+    },
+    MaterialProps {
+        material: Material::Ice,
+        name: "Ice",
+        density: 917.0,
+        color: [0.8, 0.9, 1.0, 1.0],
+        temperature: -10.0,
+        conductivity: 0.6,
+        heat_capacity: 2.0,
+        heats_into: Some((0.0, Material::Water)),
+        ..DEFAULT_PROPS
+    },
+    MaterialProps {
+        material: Material::Acid,
+        name: "Acid",
+        state: MaterialState::Liquid,
+        density: 1200.0,
+        color: [0.5, 0.95, 0.2, 1.0],
+        conductivity: 0.5,
+        heat_capacity: 3.0,
+        acid_resistance: 1.0,
+        hardness: 0.5,
+        conductive: true,
+        ..DEFAULT_PROPS
+    },
+    MaterialProps {
+        material: Material::Wall,
+        name: "Wall",
+        density: 2400.0,
+        color: [0.6, 0.6, 0.65, 1.0],
+        acid_resistance: 1.0,
+        hardness: 10.0,
+        ..DEFAULT_PROPS
+    },
+    MaterialProps {
+        material: Material::Freezer,
+        name: "Freezer",
+        density: 2400.0,
+        color: [0.55, 0.8, 0.95, 1.0],
+        temperature: -40.0,
+        conductivity: 1.0,
+        holds_temperature: true,
+        acid_resistance: 1.0,
+        hardness: 10.0,
+        ..DEFAULT_PROPS
+    },
+    MaterialProps {
+        material: Material::Sandstone,
+        name: "Sandstone",
+        density: 2300.0,
+        color: [0.85, 0.68, 0.45, 1.0],
+        conductivity: 0.3,
+        heat_capacity: 2.0,
+        acid_resistance: 0.5,
+        hardness: 3.0,
+        ..DEFAULT_PROPS
+    },
+    MaterialProps {
+        material: Material::Tnt,
+        name: "TNT",
+        density: 1650.0,
+        color: [0.8, 0.15, 0.1, 1.0],
+        flammability: 1.0,
+        ignition_point: Some(150.0),
+        hardness: 0.5,
+        blast_radius: 6.0,
+        ..DEFAULT_PROPS
+    },
+    MaterialProps {
+        material: Material::Spring,
+        name: "Spring",
+        density: 2400.0,
+        color: [0.3, 0.6, 0.8, 1.0],
+        acid_resistance: 1.0,
+        hardness: 10.0,
+        emits: Some((2, Material::Water)),
+        ..DEFAULT_PROPS
+    },
+    MaterialProps {
+        material: Material::Spout,
+        name: "Spout",
+        density: 2400.0,
+        color: [0.75, 0.6, 0.35, 1.0],
+        acid_resistance: 1.0,
+        hardness: 10.0,
+        emits: Some((2, Material::Sand)),
+        ..DEFAULT_PROPS
+    },
+    MaterialProps {
+        material: Material::Drain,
+        name: "Drain",
+        density: 2400.0,
+        color: [0.15, 0.15, 0.2, 1.0],
+        acid_resistance: 1.0,
+        hardness: 10.0,
+        drains: true,
+        ..DEFAULT_PROPS
+    },
+    MaterialProps {
+        material: Material::Seed,
+        name: "Seed",
+        state: MaterialState::Powder,
+        density: 600.0,
+        color: [0.55, 0.45, 0.25, 1.0],
+        flammability: 0.3,
+        burn_time: 5.0,
+        burns_into: Material::Fire,
+        ignition_point: Some(250.0),
+        hardness: 0.5,
+        ..DEFAULT_PROPS
+    },
+    MaterialProps {
+        material: Material::Plant,
+        name: "Plant",
+        density: 700.0,
+        color: [0.2, 0.7, 0.2, 1.0],
+        flammability: 0.2,
+        burn_time: 20.0,
+        burns_into: Material::Fire,
+        heat_capacity: 3.0,
+        ignition_point: Some(250.0),
+        acid_resistance: 0.3,
+        ..DEFAULT_PROPS
+    },
+    MaterialProps {
+        material: Material::Soil,
+        name: "Soil",
+        state: MaterialState::Powder,
+        density: 1300.0,
+        color: [0.4, 0.28, 0.18, 1.0],
+        conductivity: 0.2,
+        heat_capacity: 2.0,
+        hardness: 0.5,
+        fertile: true,
+        ..DEFAULT_PROPS
+    },
+    MaterialProps {
+        material: Material::Wire,
+        name: "Wire",
+        density: 8900.0,
+        color: [0.72, 0.45, 0.2, 1.0],
+        conductivity: 0.4,
+        acid_resistance: 0.5,
+        hardness: 2.0,
+        conductive: true,
+        ..DEFAULT_PROPS
+    },
+    MaterialProps {
+        material: Material::Metal,
+        name: "Metal",
+        density: 7800.0,
+        color: [0.6, 0.62, 0.65, 1.0],
+        conductivity: 0.9,
+        heat_capacity: 3.0,
+        acid_resistance: 0.8,
+        hardness: 6.0,
+        conductive: true,
+        ..DEFAULT_PROPS
+    },
+    MaterialProps {
+        material: Material::Battery,
+        name: "Battery",
+        density: 2000.0,
+        color: [0.2, 0.25, 0.3, 1.0],
+        acid_resistance: 1.0,
+        hardness: 4.0,
+        charges: true,
+        ..DEFAULT_PROPS
+    },
+];
+
+impl Material {
+    // Get the id of the material, which is its index in the material registry
+    pub fn id(self) -> u8 {
+        self as u8
+    }
+
+    // Look up the properties of the material in the material registry
+    pub fn props(self) -> &'static MaterialProps {
+        &MATERIALS[self.id() as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registry_is_indexed_by_id() {
+        for (i, props) in MATERIALS.iter().enumerate() {
+            assert_eq!(props.material.id() as usize, i);
+            assert_eq!(props.material.props().name, props.name);
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use world::{VoxPos, DEFAULT_WORLD_SIZE};
+
+const USAGE: &str = "Usage: sandvox [--size WIDTHxHEIGHTxDEPTH] [--seed SEED]";
+const MIN_WORLD_SIZE: usize = 4; // Smallest the voxel grid can be along each axis, to fit the player
+
+// Largest the voxel grid can be along each axis. Every grid has a table entry for each of its
+// chunks, stored or not, and this keeps those tables to about a million entries.
+const MAX_WORLD_SIZE: usize = 1024;
+
+// Settings chosen on the command line when starting the game
+pub struct Options {
+    pub world_size: VoxPos, // Number of voxels along each axis of the world
+    pub seed: u64,          // Seed that all the game's randomness is drawn from
+}
+
+// Parse a world size written as `WIDTHxHEIGHTxDEPTH`, such as `100x50x100`
+fn parse_size(s: &str) -> Result<VoxPos, String> {
+    let dims: Vec<usize> = s
+        .split('x')
+        .map(|dim| dim.parse())
+        .collect::<Result<_, _>>()
+        .map_err(|_| format!("Invalid world size `{}`\n{}", s, USAGE))?;
+    let in_range = |dim: usize| (MIN_WORLD_SIZE..=MAX_WORLD_SIZE).contains(&dim);
+    match dims[..] {
+        [x, y, z] if in_range(x) && in_range(y) && in_range(z) => Ok((x, y, z)),
+        [_, _, _] => Err(format!(
+            "World size `{}` must be between {} and {} along each axis",
+            s, MIN_WORLD_SIZE, MAX_WORLD_SIZE
+        )),
+        _ => Err(format!("Invalid world size `{}`\n{}", s, USAGE)),
+    }
+}
+
+// Parse the command line arguments that come after the program name
+pub fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+    let mut options = Options {
+        world_size: DEFAULT_WORLD_SIZE,
+        seed: 0,
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--size" => {
+                let size = args
+                    .next()
+                    .ok_or(format!("Missing world size\n{}", USAGE))?;
+                options.world_size = parse_size(&size)?;
+            }
+            "--seed" => {
+                let seed = args.next().ok_or(format!("Missing seed\n{}", USAGE))?;
+                options.seed = seed
+                    .parse()
+                    .map_err(|_| format!("Invalid seed `{}`\n{}", seed, USAGE))?;
+            }
+            _ => return Err(format!("Unknown argument `{}`\n{}", arg, USAGE)),
+        }
+    }
+    Ok(options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn world_size_defaults_when_not_given() {
+        assert_eq!(parse(&[]).unwrap().world_size, DEFAULT_WORLD_SIZE);
+    }
+
+    #[test]
+    fn parses_seed() {
+        let options = parse(&["--seed", "12345", "--size", "60x60x60"]).unwrap();
+        assert_eq!(options.seed, 12345);
+        assert_eq!(options.world_size, (60, 60, 60));
+        assert_eq!(parse(&[]).unwrap().seed, 0);
+        assert!(parse(&["--seed"]).is_err());
+        assert!(parse(&["--seed", "-1"]).is_err());
+    }
+
+    #[test]
+    fn parses_world_size() {
+        let options = parse(&["--size", "200x60x300"]).unwrap();
+        assert_eq!(options.world_size, (200, 60, 300));
+    }
+
+    #[test]
+    fn rejects_bad_world_sizes() {
+        assert!(parse(&["--size"]).is_err());
+        assert!(parse(&["--size", "100x100"]).is_err());
+        assert!(parse(&["--size", "100xtallx100"]).is_err());
+        assert!(parse(&["--size", "100x2x100"]).is_err());
+        assert!(parse(&["--size", "100x100x5000"]).is_err());
+        assert!(parse(&["--frobnicate"]).is_err());
+    }
+}
@@ -5,10 +5,21 @@ use clamp::clamp;
 
 use nd_iter::iter_3d;
 
+use std::cmp::Reverse;
+use std::ops::Range;
+
 use rand::prelude::*;
+use rand_xorshift::XorShiftRng;
+
+use rayon::prelude::*;
 
-use client::{GameState, Player, PlayerState, Voxel, VOX_MAX_X, VOX_MAX_Y, VOX_MAX_Z};
+use client::{
+    AwakeSet, Charge, GameState, Particle, Player, PlayerState, PressureGrid, TempGrid, Voxel,
+    VoxelGrid,
+};
+use material::{Material, MaterialState, ROOM_TEMP};
 use render::VoxInd;
+use world::{Grid, VoxPos, CHUNK_SIZE, CHUNK_VOLUME};
 
 const EYE_HEIGHT: f32 = 1.62; // Height of the player's eyes
 const FOREHEAD_SIZE: f32 = 0.2; // Vertical distance from the player's eyes to the top of the player
@@ -21,83 +32,109 @@ const FLY_SPEED: f32 = 30.0;
 const WALK_SPEED: f32 = 4.3;
 const RUN_SPEED: f32 = 6.6;
 
-// Determine if the voxel at `pos` is a boundary (one voxel outside the voxel grid)
-fn boundary_at_pos(pos: Point3<f32>) -> bool {
+pub const STEP_TIME: f32 = 1.0 / 60.0; // Duration of a fixed physics step, in seconds
+const STEPS_PER_TICK: u32 = 10; // Physics steps per voxel simulation tick at normal speed
+
+// Speeds the voxel simulation can run at, in quarters of normal speed (0.25x to 8x)
+const SIM_SPEEDS: [u32; 6] = [1, 2, 4, 8, 16, 32];
+pub const NORMAL_SPEED: usize = 2; // Index of normal speed in `SIM_SPEEDS`
+const TICK_PROGRESS: u32 = 4 * STEPS_PER_TICK; // Progress needed for a tick, in `SIM_SPEEDS` units
+
+const FLAME_CHANCE: f32 = 0.3; // Chance per tick of a burning voxel setting the air next to it aflame
+const FLAME_TEMP: f32 = 800.0; // Temperature of burning voxels, in degrees Celsius
+const AIR_COOLING: f32 = 0.05; // Fraction of the difference from room temperature that air loses per tick
+const AIR_SNAP: f32 = 0.01; // How close to room temperature air has to be to settle at it
+const FLOW_DEPTH: f32 = 0.5; // Depth of liquid, in voxels, that pushes a liquid sideways
+const ACID_STRENGTH: f32 = 0.2; // Chance per tick of acid dissolving a voxel with no acid resistance
+const GROW_CHANCE: f32 = 0.1; // Chance per tick of a watered plant growing
+const PLANT_HEIGHT: usize = 8; // Height, in voxels, that plants stop growing at
+const ELECTRIC_HEAT: f32 = 20.0; // Heat given to a voxel each time it's charged
+const BLAST_SPEED: f32 = 2.0; // Speed of voxels thrown by the full strength of a blast, in voxels per tick
+const PARTICLE_GRAVITY: f32 = 0.3; // Acceleration of flying voxels, in voxels per tick^2
+const KNOCKBACK_SPEED: f32 = 15.0; // Speed the full strength of a blast knocks the player back at
+const KNOCKBACK_REACH: f32 = 2.0; // How far a blast knocks the player back, as a multiple of its radius
+const LOAD_DISTANCE: usize = 4; // Chunks kept loaded around the player's chunk in each direction
+
+// Determine if the voxel at `pos` is a boundary (one voxel outside a voxel grid of `size`)
+fn boundary_at_pos((sx, sy, sz): VoxPos, pos: Point3<f32>) -> bool {
     pos.x as i32 == -1
         || pos.y as i32 == -1
         || pos.z as i32 == -1
-        || pos.x as usize == VOX_MAX_X
-        || pos.y as usize == VOX_MAX_Y
-        || pos.z as usize == VOX_MAX_Z
+        || pos.x as usize == sx
+        || pos.y as usize == sy
+        || pos.z as usize == sz
 }
 
 // Get the voxel at `pos`, returning `None` when the position isn't
 // within the bounds of the voxel grid. Note that the boundary (one
 // outside the voxel grid) is considered a voxel.
-fn voxel_at_opt(state: &GameState, pos: Point3<f32>) -> Option<Voxel> {
-    if boundary_at_pos(pos) {
-        Some(Voxel::Boundary)
+fn voxel_at_opt(voxels: &VoxelGrid, pos: Point3<f32>) -> Option<Voxel> {
+    if boundary_at_pos(voxels.size(), pos) {
+        Some(Voxel::BOUNDARY)
     } else {
-        Some(
-            *state
-                .voxels
-                .get(pos.x as usize)?
-                .get(pos.y as usize)?
-                .get(pos.z as usize)?,
-        )
+        voxels
+            .get((pos.x as usize, pos.y as usize, pos.z as usize))
+            .cloned()
     }
 }
 
 // Determine if there is a non-air voxel at `pos`, returning `false` when the position is out of
 // bounds
 pub fn voxel_at(state: &GameState, pos: Point3<f32>) -> bool {
-    voxel_at_opt(state, pos)
+    voxel_at_opt(&state.voxels, pos)
         .map(|vox| !vox.is_air())
         .unwrap_or(false)
 }
 
-// Set a voxel at a coordinate, returning `None` if out-of-bounds
+// Determine if there is a voxel at `pos` that the player collides with, returning `false` when
+// the position is out of bounds. The player collides with solids, powders and the boundary, but
+// falls through liquids and gases.
+fn collides_at(voxels: &VoxelGrid, pos: Point3<f32>) -> bool {
+    voxel_at_opt(voxels, pos)
+        .map(|vox| match vox.props().state {
+            MaterialState::Solid | MaterialState::Powder => true,
+            MaterialState::Liquid | MaterialState::Gas => false,
+        })
+        .unwrap_or(false)
+}
+
+// Set a voxel at a coordinate, returning `None` if out-of-bounds. The voxel starts at its
+// material's initial temperature.
 pub fn put_voxel(state: &mut GameState, pos: Point3<VoxInd>, voxel: Voxel) -> Option<()> {
-    *state
-        .voxels
-        .get_mut(pos.x as usize)?
-        .get_mut(pos.y as usize)?
-        .get_mut(pos.z as usize)? = voxel;
+    let pos = (pos.x as usize, pos.y as usize, pos.z as usize);
+    if !state.voxels.contains(pos) {
+        return None;
+    }
+    state.voxels[pos] = voxel;
+    state.temps[pos] = voxel.props().temperature;
+    wake_around(&mut state.awake, state.voxels.size(), pos, true);
     state.dirty = true;
     Some(())
 }
 
 pub fn player_in_freefall(state: &GameState) -> bool {
-    !player_is_standing(state) && state.player.state != PlayerState::Flying
+    !player_is_standing(&state.voxels, &state.player) && state.player.state != PlayerState::Flying
 }
 
-// Is the player standing on the bottom of the voxel grid or sand?
-fn player_is_standing(state: &GameState) -> bool {
-    let foot_pos = state.player.pos - Vector3::new(0.0, EYE_HEIGHT, 0.0);
+// Is the player standing on the bottom of the voxel grid or something solid?
+fn player_is_standing(voxels: &VoxelGrid, player: &Player) -> bool {
+    let foot_pos = player.pos - Vector3::new(0.0, EYE_HEIGHT, 0.0);
     let surface_pos = foot_pos - Vector3::new(0.0, 1.0, 0.0);
-    voxel_at(state, surface_pos)
+    collides_at(voxels, surface_pos)
 }
 
-// Clip the player inside the bounds of the voxel grid
-fn bounds_correct_player(player: &mut Player) {
-    player.pos.x = clamp(
-        PLAYER_RADIUS,
-        player.pos.x,
-        VOX_MAX_X as f32 - PLAYER_RADIUS,
-    );
-    player.pos.y = clamp(EYE_HEIGHT, player.pos.y, VOX_MAX_Y as f32 - FOREHEAD_SIZE);
-    player.pos.z = clamp(
-        PLAYER_RADIUS,
-        player.pos.z,
-        VOX_MAX_Z as f32 - PLAYER_RADIUS,
-    );
+// Clip the player inside the bounds of a voxel grid of `size`
+fn bounds_correct_player(player: &mut Player, (sx, sy, sz): VoxPos) {
+    player.pos.x = clamp(PLAYER_RADIUS, player.pos.x, sx as f32 - PLAYER_RADIUS);
+    player.pos.y = clamp(EYE_HEIGHT, player.pos.y, sy as f32 - FOREHEAD_SIZE);
+    player.pos.z = clamp(PLAYER_RADIUS, player.pos.z, sz as f32 - PLAYER_RADIUS);
 }
 
-// Update player position and velocity
-pub fn do_player_physics(player: &mut Player, dt: f32) {
+// Update player position and velocity within a voxel grid of `size`
+pub fn do_player_physics(player: &mut Player, size: VoxPos, dt: f32) {
     player.pos += player.velocity * dt;
     // TODO: Prevent player from clipping inside sand
-    bounds_correct_player(player);
+    bounds_correct_player(player, size);
     player.velocity.y -= ACCEL_GRAV * dt;
 }
 
@@ -107,87 +144,2967 @@ fn get_rand_dir<T: Rng>(rng: &mut T) -> (i32, i32) {
     (rng.gen_range(-1, 1 + 1), rng.gen_range(-1, 1 + 1))
 }
 
-// Propagate the voxels downwards (gravity)
-// TODO: Somehow use `dt` here
-pub fn do_sandfall(state: &mut GameState) {
-    if state.frame % 10 == 0 {
-        for (x, y, z) in iter_3d(0..VOX_MAX_X, 1..VOX_MAX_Y, 0..VOX_MAX_Z) {
-            // TODO: Make this less boilerplate
-            let hi = state.voxels[x][y][z];
-            let lo = state.voxels[x][y - 1][z];
-            // Try direct up-down swap
-            if !hi.is_air() && lo.is_air() {
-                state.voxels[x][y][z] = lo;
-                state.voxels[x][y - 1][z] = hi;
-                state.dirty = true;
-            } else if x != 0 && state.voxels[x - 1][y - 1][z].is_air()
-                || z != 0 && state.voxels[x][y - 1][z - 1].is_air()
-                || x != 0 && z != 0 && state.voxels[x - 1][y - 1][z - 1].is_air()
-                || x != VOX_MAX_X - 1 && state.voxels[x + 1][y - 1][z].is_air()
-                || z != VOX_MAX_Z - 1 && state.voxels[x][y - 1][z + 1].is_air()
-                || x != VOX_MAX_X - 1
-                    && z != VOX_MAX_Z - 1
-                    && state.voxels[x + 1][y - 1][z + 1].is_air()
+// Get a random direction along a 2D plane towards a cell beside the voxel at `pos` that the voxel
+// can displace, or `None` if there's none. Liquid under pressure squeezes into any such cell,
+// which lets it find its way along narrow pipes.
+fn get_open_side<T: Rng>(voxels: &VoxelGrid, pos: VoxPos, rng: &mut T) -> Option<(i32, i32)> {
+    let (x, y, z) = pos;
+    let open: Vec<(i32, i32)> = [(-1, 0), (1, 0), (0, -1), (0, 1)]
+        .iter()
+        .cloned()
+        .filter(|&(dx, dz)| {
+            voxels
+                .offset(pos, (dx, 0, dz))
+                .filter(|&(ox, oy, oz)| can_displace(voxels[(x, y, z)], voxels[(ox, oy, oz)]))
+                .is_some()
+        })
+        .collect();
+    open.choose(rng).cloned()
+}
+
+// Kinds of sandfall moves, in order of precedence. When several voxels want to move into the
+// same cell, the voxel with the kind declared first wins.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+enum MoveKind {
+    Push,  // Sideways, or between surfaces of a liquid, for liquids pushed by pressure
+    Fall,  // Straight down, or straight up for gases
+    Slide, // Sideways-down, or sideways-up for gases
+    Flow,  // Sideways, for fluids
+}
+
+// A request for the voxel at `src` to move to `dst` during a sandfall step. Whatever is at `dst`
+// ends up at `src`.
+#[derive(Copy, Clone, Debug)]
+struct SandMove {
+    kind: MoveKind,
+    rank: u64, // Random tiebreaker for moves of the same kind
+    src: VoxPos,
+    dst: VoxPos,
+}
+
+// Mix the step seed with a voxel position. This gives each voxel its own randomness that doesn't
+// depend on the order the voxel grid is visited in.
+fn hash_pos(seed: u64, (x, y, z): VoxPos) -> u64 {
+    // Finalizer from SplitMix64
+    let mut h = seed ^ x as u64 ^ ((y as u64) << 21) ^ ((z as u64) << 42);
+    h = (h ^ (h >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    h = (h ^ (h >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    h ^ (h >> 31)
+}
+
+// Determine if `voxel` can move into the cell occupied by `other`, swapping places with it. Only
+// fluids can be displaced, and only by something heavier sinking into them, or something lighter
+// rising into them for gases.
+fn can_displace(voxel: Voxel, other: Voxel) -> bool {
+    let props = voxel.props();
+    let other_props = other.props();
+    match other_props.state {
+        MaterialState::Liquid | MaterialState::Gas => {
+            if props.state == MaterialState::Gas {
+                props.density < other_props.density
+            } else {
+                props.density > other_props.density
+            }
+        }
+        MaterialState::Solid | MaterialState::Powder => false,
+    }
+}
+
+// Determine if the fluid voxel at `pos` is spreading sideways. A fluid spreads when it is pushed
+// by more of the same kind of fluid or is resting on it, so puddles level out into a single layer
+// and then come to rest.
+fn fluid_is_spreading(voxels: &VoxelGrid, (x, y, z): VoxPos) -> bool {
+    let state = voxels[(x, y, z)].props().state;
+    let is_same = |voxel: Voxel| !voxel.is_air() && voxel.props().state == state;
+    voxels.contains((x, y + 1, z)) && is_same(voxels[(x, y + 1, z)])
+        || y > 0 && is_same(voxels[(x, y - 1, z)])
+}
+
+// Determine if a voxel is a liquid under enough pressure to flow along a pipe
+fn is_pressurized(voxel: Voxel, pressure: f32) -> bool {
+    let props = voxel.props();
+    props.state == MaterialState::Liquid && pressure > FLOW_DEPTH * props.density
+}
+
+// Get the layer a voxel at height `y` falls (or rises) into, which is `None` at the edge of a grid
+// `height` voxels tall. Returns `None` if the voxel doesn't move at all, such as air and solids
+// that don't crumble.
+fn get_next_layer(voxel: Voxel, y: usize, height: usize) -> Option<Option<usize>> {
+    let props = voxel.props();
+    match props.state {
+        MaterialState::Powder | MaterialState::Liquid => Some(y.checked_sub(1)),
+        MaterialState::Solid if props.crumbles => Some(y.checked_sub(1)),
+        MaterialState::Gas if !voxel.is_air() => Some(Some(y + 1).filter(|&y| y < height)),
+        _ => None,
+    }
+}
+
+// Determine where the voxel at `pos` wants to move, only looking at the previous grid. Powders
+// fall straight down, or sideways-down when blocked. Liquids do the same, but can also flow
+// sideways, which they do on their own when under pressure. Gases move like liquids, but upwards.
+// Powders and gases with dispersion drift sideways at random while falling or rising through a
+// fluid. Solids stay in place, except for crumbling solids, which fall straight down when nothing
+// is holding them up.
+fn get_sand_move(
+    voxels: &VoxelGrid,
+    pressures: &PressureGrid,
+    seed: u64,
+    pos: VoxPos,
+) -> Option<SandMove> {
+    let (x, y, z) = pos;
+    let voxel = voxels[(x, y, z)];
+    let props = voxel.props();
+    let state = props.state;
+    let y_next = get_next_layer(voxel, y, voxels.size().1)?;
+    let rank = hash_pos(seed, pos);
+    let mut rng = XorShiftRng::seed_from_u64(rank);
+    // Viscous fluids only move some of the time
+    if props.viscosity > 0.0 && rng.gen::<f32>() < props.viscosity {
+        return None;
+    }
+    let make_move = |kind, dst| {
+        Some(SandMove {
+            kind,
+            rank,
+            src: pos,
+            dst,
+        })
+    };
+    let airborne = y_next
+        .filter(|&y_next| can_displace(voxel, voxels[(x, y_next, z)]))
+        .is_some();
+    // Dispersive voxels drift sideways at random instead of falling (or rising) straight
+    let drifting = airborne && props.dispersion > 0.0 && rng.gen::<f32>() < props.dispersion;
+    // Try direct up-down swap
+    if let Some(y_next) = y_next.filter(|_| airborne && !drifting) {
+        return make_move(MoveKind::Fall, (x, y_next, z));
+    }
+    if state == MaterialState::Solid {
+        return None;
+    }
+    let pressurized = is_pressurized(voxel, pressures[(x, y, z)]);
+    let (dx, dz) = if pressurized {
+        get_open_side(voxels, pos, &mut rng).unwrap_or((0, 0))
+    } else {
+        get_rand_dir(&mut rng)
+    };
+    let (x_alt, _, z_alt) = match voxels.offset(pos, (dx, 0, dz)) {
+        Some(alt) if (dx, dz) != (0, 0) => alt,
+        _ => return None,
+    };
+    match y_next.filter(|_| !drifting) {
+        // Try moving sideways-down
+        Some(y_next) if can_displace(voxel, voxels[(x_alt, y_next, z_alt)]) => {
+            make_move(MoveKind::Slide, (x_alt, y_next, z_alt))
+        }
+        // Try flowing (or drifting) sideways
+        _ if can_displace(voxel, voxels[(x_alt, y, z_alt)])
+            && (drifting
+                || pressurized
+                || state != MaterialState::Powder && fluid_is_spreading(voxels, pos)) =>
+        {
+            let kind = if pressurized {
+                MoveKind::Push
+            } else {
+                MoveKind::Flow
+            };
+            make_move(kind, (x_alt, y, z_alt))
+        }
+        _ => None,
+    }
+}
+
+// Pick the sandfall moves that happen this step out of the moves the voxels want to make. Moves
+// are considered in order of precedence (see `MoveKind`), with ties broken by a random rank, and a
+// move is dropped if a move before it already involves either of its cells. When two voxels want
+// the same cell, the loser stays put until the next step. This makes the result independent of the
+// order the moves were requested in.
+fn resolve_moves(mut moves: Vec<SandMove>, size: VoxPos) -> Vec<SandMove> {
+    moves.sort_by_key(|mv| (mv.kind, mv.rank, mv.src));
+    let mut claimed = Grid::new(size, false);
+    moves.retain(|&SandMove { src, dst, .. }| {
+        let (sx, sy, sz) = src;
+        let (dx, dy, dz) = dst;
+        let free = !claimed[(sx, sy, sz)] && !claimed[(dx, dy, dz)];
+        if free {
+            claimed[(sx, sy, sz)] = true;
+            claimed[(dx, dy, dz)] = true;
+        }
+        free
+    });
+    moves
+}
+
+// Determine if the voxel at `pos` could move in a sandfall step, whatever the roll, by following the
+// same rules as `get_sand_move`. Voxels that can't move have settled.
+fn is_unsettled(voxels: &VoxelGrid, pressures: &PressureGrid, pos: VoxPos) -> bool {
+    let (x, y, z) = pos;
+    let voxel = voxels[(x, y, z)];
+    let state = voxel.props().state;
+    let y_next = match get_next_layer(voxel, y, voxels.size().1) {
+        Some(y_next) => y_next,
+        None => return false,
+    };
+    let opens = |(ox, oy, oz): VoxPos| can_displace(voxel, voxels[(ox, oy, oz)]);
+    if y_next.filter(|&y_next| opens((x, y_next, z))).is_some() {
+        return true;
+    }
+    if state == MaterialState::Solid {
+        return false;
+    }
+    // Liquid under pressure only squeezes sideways (see `get_open_side`)
+    if is_pressurized(voxel, pressures[(x, y, z)]) {
+        return [(-1, 0, 0), (1, 0, 0), (0, 0, -1), (0, 0, 1)]
+            .iter()
+            .filter_map(|&offset| voxels.offset(pos, offset))
+            .any(opens);
+    }
+    let spreading = state != MaterialState::Powder && fluid_is_spreading(voxels, pos);
+    iter_3d(-1..2, 0..1, -1..2)
+        .filter_map(|offset| voxels.offset(pos, offset))
+        .filter(|&side| side != pos)
+        .any(|(ox, _, oz)| {
+            y_next.filter(|&y_next| opens((ox, y_next, oz))).is_some()
+                || spreading && opens((ox, y, oz))
+        })
+}
+
+// Compute the moves of the next sandfall step of the voxels at `cells`. Voxels only look at the
+// previous grid when deciding where to move, and each voxel moves at most one cell per step.
+// Returns the moves along with the pressures they were decided with.
+fn get_sand_moves(
+    voxels: &VoxelGrid,
+    cells: &[VoxPos],
+    seed: u64,
+) -> (Vec<SandMove>, PressureGrid) {
+    let (pressures, mut moves) = get_pressures(voxels, cells, seed);
+    moves.extend(par_flat_map(cells, |pos| {
+        get_sand_move(voxels, &pressures, seed, pos)
+    }));
+    (resolve_moves(moves, voxels.size()), pressures)
+}
+
+// Apply sandfall moves to a grid by swapping the contents of each move's cells. No two moves share
+// a cell (see `resolve_moves`), so they can be applied in place in any order. This is used for the
+// voxels and for the fields that travel with them, such as temperature.
+fn apply_moves<T: Copy>(grid: &mut Grid<T>, moves: &[SandMove]) {
+    for &SandMove { src, dst, .. } in moves {
+        let (sx, sy, sz) = src;
+        let (dx, dy, dz) = dst;
+        let value = grid[(sx, sy, sz)];
+        grid[(sx, sy, sz)] = grid[(dx, dy, dz)];
+        grid[(dx, dy, dz)] = value;
+    }
+}
+
+// A reaction between two touching voxels. Each tick that a voxel of material `a` touches a voxel
+// of material `b`, there is a `chance` of `a` turning into `a_into` and `b` turning into `b_into`,
+// where `None` leaves the voxel unchanged.
+struct Reaction {
+    a: Material,
+    b: Material,
+    a_into: Option<Material>,
+    b_into: Option<Material>,
+    chance: f32,
+}
+
+// Reactions between touching voxels
+const REACTIONS: [Reaction; 5] = [
+    // Lava cools into stone in water, boiling the water
+    Reaction {
+        a: Material::Lava,
+        b: Material::Water,
+        a_into: Some(Material::Stone),
+        b_into: Some(Material::Steam),
+        chance: 0.5,
+    },
+    // Lava melts sand into glass
+    Reaction {
+        a: Material::Lava,
+        b: Material::Sand,
+        a_into: None,
+        b_into: Some(Material::Glass),
+        chance: 0.02,
+    },
+    // Lava sets flammable materials on fire
+    Reaction {
+        a: Material::Lava,
+        b: Material::Wood,
+        a_into: None,
+        b_into: Some(Material::Fire),
+        chance: 0.1,
+    },
+    Reaction {
+        a: Material::Lava,
+        b: Material::Oil,
+        a_into: None,
+        b_into: Some(Material::Fire),
+        chance: 0.5,
+    },
+    Reaction {
+        a: Material::Lava,
+        b: Material::Dust,
+        a_into: None,
+        b_into: Some(Material::Fire),
+        chance: 0.5,
+    },
+];
+
+// Offsets of the neighbors sharing a face with a voxel
+const FACE_OFFSETS: [(i32, i32, i32); 6] = [
+    (-1, 0, 0),
+    (1, 0, 0),
+    (0, -1, 0),
+    (0, 1, 0),
+    (0, 0, -1),
+    (0, 0, 1),
+];
+
+// Get the voxels sharing a face with the voxel at `pos`
+fn get_neighbors<'a>(voxels: &'a VoxelGrid, pos: VoxPos) -> impl Iterator<Item = Voxel> + 'a {
+    FACE_OFFSETS
+        .iter()
+        .filter_map(move |&offset| voxels.offset(pos, offset))
+        .map(move |(x, y, z)| voxels[(x, y, z)])
+}
+
+// Roll a random number from 0.0 to 1.0 for the pair of neighboring voxels at `p` and `q`. Both
+// voxels get the same roll, so they agree on whether a reaction between them happens.
+fn roll_pair(seed: u64, p: VoxPos, q: VoxPos) -> f32 {
+    let (lo, hi) = if p < q { (p, q) } else { (q, p) };
+    let h = hash_pos(hash_pos(seed, lo), hi);
+    (h >> 40) as f32 / (1 << 24) as f32
+}
+
+// Get what the voxel at `pos` turns into from reacting with one of its neighbors, or `None` if it
+// doesn't react this tick
+fn get_reaction_product(voxels: &VoxelGrid, seed: u64, pos: VoxPos) -> Option<Material> {
+    let (x, y, z) = pos;
+    let material = voxels[(x, y, z)].material;
+    for q in FACE_OFFSETS
+        .iter()
+        .filter_map(|&offset| voxels.offset(pos, offset))
+    {
+        let (qx, qy, qz) = q;
+        let other = voxels[(qx, qy, qz)].material;
+        for reaction in REACTIONS.iter() {
+            let product = if (reaction.a, reaction.b) == (material, other) {
+                reaction.a_into
+            } else if (reaction.b, reaction.a) == (material, other) {
+                reaction.b_into
+            } else {
+                None
+            };
+            if product.is_some() && roll_pair(seed, pos, q) < reaction.chance {
+                return product;
+            }
+        }
+    }
+    None
+}
+
+// Get the position of the voxel that the acid at `acid_pos` dissolves this tick, or `None` if it
+// doesn't dissolve anything. Each tick, acid picks one of its neighbors at random and dissolves it
+// unless the neighbor resists. This is decided by the acid's own roll, so the acid and the voxel it
+// dissolves agree on what happens.
+fn get_acid_target(voxels: &VoxelGrid, seed: u64, acid_pos: VoxPos) -> Option<VoxPos> {
+    let mut rng = XorShiftRng::seed_from_u64(hash_pos(seed, acid_pos));
+    let offset = *FACE_OFFSETS.choose(&mut rng)?;
+    let (x, y, z) = voxels.offset(acid_pos, offset)?;
+    let resistance = voxels[(x, y, z)].props().acid_resistance;
+    if rng.gen::<f32>() < ACID_STRENGTH * (1.0 - resistance) {
+        Some((x, y, z))
+    } else {
+        None
+    }
+}
+
+// Determine if the voxel at `pos` is dissolved by a neighboring acid this tick
+fn is_dissolved(voxels: &VoxelGrid, seed: u64, pos: VoxPos) -> bool {
+    FACE_OFFSETS
+        .iter()
+        .filter_map(|&offset| voxels.offset(pos, offset))
+        .any(|(x, y, z)| {
+            voxels[(x, y, z)].material == Material::Acid
+                && get_acid_target(voxels, seed, (x, y, z)) == Some(pos)
+        })
+}
+
+// Determine if a voxel flows into a drain it touches. Fluids and powders are drained, but solids
+// aren't.
+fn is_drainable(voxel: Voxel) -> bool {
+    !voxel.is_air() && voxel.props().state != MaterialState::Solid
+}
+
+// Determine if the voxel at `pos` flows into a neighboring drain this tick
+fn is_drained(voxels: &VoxelGrid, pos: VoxPos) -> bool {
+    let (x, y, z) = pos;
+    is_drainable(voxels[(x, y, z)]) && get_neighbors(voxels, pos).any(|vox| vox.props().drains)
+}
+
+// Determine if a voxel is part of a plant, counting seeds that haven't sprouted yet
+fn is_plant(voxel: Voxel) -> bool {
+    voxel.material == Material::Seed || voxel.material == Material::Plant
+}
+
+// Get the bottom of the stalk containing the plant voxel at `pos`
+fn get_plant_root(voxels: &VoxelGrid, (x, y, z): VoxPos) -> VoxPos {
+    let mut y = y;
+    while y > 0 && voxels[(x, y - 1, z)].material == Material::Plant {
+        y -= 1;
+    }
+    (x, y, z)
+}
+
+// Get the top of the stalk containing the plant voxel at `pos`
+fn get_plant_tip(voxels: &VoxelGrid, (x, y, z): VoxPos) -> VoxPos {
+    let mut y = y;
+    while voxels.contains((x, y + 1, z)) && voxels[(x, y + 1, z)].material == Material::Plant {
+        y += 1;
+    }
+    (x, y, z)
+}
+
+// Get the water that the plant rooted at `root` drinks to grow this tick, or `None` if it doesn't
+// grow. Plants grow from seeds resting on fertile ground, and drink water touching their root or
+// the ground under it. Each time a plant grows, either its seed sprouts or its stalk grows one voxel
+// taller, until it's `PLANT_HEIGHT` tall or something is in the way.
+fn get_growth_water(voxels: &VoxelGrid, seed: u64, root: VoxPos) -> Option<VoxPos> {
+    let mut rng = XorShiftRng::seed_from_u64(hash_pos(seed, root));
+    if rng.gen::<f32>() >= GROW_CHANCE {
+        return None;
+    }
+    find_growth_water(voxels, root)
+}
+
+// Get the water that the plant rooted at `root` would drink if it grew, or `None` if it can't grow
+fn find_growth_water(voxels: &VoxelGrid, root: VoxPos) -> Option<VoxPos> {
+    let (x, y, z) = root;
+    if y == 0 || !is_plant(voxels[(x, y, z)]) || !voxels[(x, y - 1, z)].props().fertile {
+        return None;
+    }
+    if voxels[(x, y, z)].material == Material::Plant {
+        let (_, tip_y, _) = get_plant_tip(voxels, root);
+        if tip_y + 1 - y >= PLANT_HEIGHT
+            || !voxels.contains((x, tip_y + 1, z))
+            || !voxels[(x, tip_y + 1, z)].is_air()
+        {
+            return None;
+        }
+    }
+    [root, (x, y - 1, z)]
+        .iter()
+        .flat_map(|&pos| {
+            FACE_OFFSETS
+                .iter()
+                .filter_map(move |&offset| voxels.offset(pos, offset))
+        })
+        .find(|&(wx, wy, wz)| voxels[(wx, wy, wz)].material == Material::Water)
+}
+
+// Get the roots of the plants that could drink the water at `pos`, which are the plant voxels
+// touching it and the plant voxels resting on fertile ground touching it
+fn get_watered_roots<'a>(voxels: &'a VoxelGrid, pos: VoxPos) -> impl Iterator<Item = VoxPos> + 'a {
+    FACE_OFFSETS
+        .iter()
+        .filter_map(move |&offset| voxels.offset(pos, offset))
+        .filter_map(move |(x, y, z)| {
+            if is_plant(voxels[(x, y, z)]) {
+                Some((x, y, z))
+            } else if voxels[(x, y, z)].props().fertile {
+                voxels
+                    .offset((x, y, z), (0, 1, 0))
+                    .filter(|&(x, y, z)| is_plant(voxels[(x, y, z)]))
+            } else {
+                None
+            }
+        })
+}
+
+// Determine if the water at `pos` is drunk by a growing plant this tick
+fn is_drunk(voxels: &VoxelGrid, seed: u64, pos: VoxPos) -> bool {
+    let (x, y, z) = pos;
+    voxels[(x, y, z)].material == Material::Water
+        && get_watered_roots(voxels, pos)
+            .any(|root| get_growth_water(voxels, seed, root) == Some(pos))
+}
+
+// Determine if the voxel at `pos` becomes part of a plant this tick, either by being a seed that
+// sprouts or by being the air that a stalk grows into
+fn is_grown_into(voxels: &VoxelGrid, seed: u64, pos: VoxPos) -> bool {
+    let (x, y, z) = pos;
+    let voxel = voxels[(x, y, z)];
+    if voxel.material == Material::Seed {
+        get_growth_water(voxels, seed, pos).is_some()
+    } else if voxel.is_air() && y > 0 && voxels[(x, y - 1, z)].material == Material::Plant {
+        let root = get_plant_root(voxels, (x, y - 1, z));
+        get_growth_water(voxels, seed, root).is_some()
+    } else {
+        false
+    }
+}
+
+// Determine if the plant voxel at `pos` is buried under a powder, which kills it
+fn is_buried(voxels: &VoxelGrid, (x, y, z): VoxPos) -> bool {
+    voxels[(x, y, z)].material == Material::Plant
+        && voxels.contains((x, y + 1, z))
+        && voxels[(x, y + 1, z)].props().state == MaterialState::Powder
+}
+
+// Compute the next state of the voxel at `pos` from itself and its neighbors in the previous grid.
+// Acid dissolves its neighbors and is used up doing so, drains swallow their neighbors, burning
+// voxels eventually burn out, gases eventually dissipate, plants drink water to grow and die when
+// buried, voxels react with their neighbors according to `REACTIONS`, flammable voxels catch fire
+// from burning or charged neighbors, and burning voxels give off flames into the air around them.
+fn react_voxel(voxels: &VoxelGrid, seed: u64, pos: VoxPos) -> Voxel {
+    let (x, y, z) = pos;
+    let voxel = voxels[(x, y, z)];
+    let props = voxel.props();
+    let mut rng = XorShiftRng::seed_from_u64(hash_pos(seed, pos));
+    if (voxel.material == Material::Acid && get_acid_target(voxels, seed, pos).is_some())
+        || is_dissolved(voxels, seed, pos)
+        || is_drained(voxels, pos)
+    {
+        Voxel::AIR
+    } else if voxel.is_burning() {
+        // Burn out, unless the voxel is an explosive that's about to go off
+        if props.blast_radius == 0.0 && rng.gen::<f32>() * props.burn_time < 1.0 {
+            Voxel::new(props.burns_into, voxel.shade)
+        } else {
+            voxel
+        }
+    } else if props.lifetime > 0.0 && rng.gen::<f32>() * props.lifetime < 1.0 {
+        // Dissipate
+        Voxel::AIR
+    } else if is_drunk(voxels, seed, pos) {
+        Voxel::AIR
+    } else if is_grown_into(voxels, seed, pos) {
+        Voxel::new(Material::Plant, rng.gen())
+    } else if is_buried(voxels, pos) {
+        // Die and rot into soil
+        Voxel::new(Material::Soil, voxel.shade)
+    } else if let Some(material) = get_reaction_product(voxels, seed, pos) {
+        Voxel::new(material, voxel.shade)
+    } else if props.flammability > 0.0
+        && get_neighbors(voxels, pos).any(|vox| vox.is_burning() || vox.charge == Charge::Charged)
+        && rng.gen::<f32>() < props.flammability
+    {
+        // Catch fire
+        Voxel {
+            burning: true,
+            ..voxel
+        }
+    } else if voxel.is_air()
+        && get_neighbors(voxels, pos).any(|vox| vox.burning)
+        && rng.gen::<f32>() < FLAME_CHANCE
+    {
+        // Give off a flame
+        Voxel::new(Material::Fire, rng.gen())
+    } else {
+        voxel
+    }
+}
+
+// Determine if the voxel at `pos` can change itself or its neighbors, given what it touches now.
+// Only reactive voxels and their neighbors need to be visited during a reaction step, and a chunk
+// without any can fall asleep.
+fn is_reactive(voxels: &VoxelGrid, pos: VoxPos) -> bool {
+    let (x, y, z) = pos;
+    let voxel = voxels[(x, y, z)];
+    !voxel.is_air()
+        && (voxel.is_burning()
+            || voxel.charge == Charge::Charged
+            || voxel.material == Material::Acid
+                && get_neighbors(voxels, pos).any(|vox| vox.props().acid_resistance < 1.0)
+            || voxel.props().drains && get_neighbors(voxels, pos).any(is_drainable)
+            || is_plant(voxel)
+                && (find_growth_water(voxels, get_plant_root(voxels, pos)).is_some()
+                    || is_buried(voxels, pos))
+            || voxel.material == Material::Water
+                && get_watered_roots(voxels, pos)
+                    .any(|root| find_growth_water(voxels, root) == Some(pos))
+            || voxel.props().lifetime > 0.0
+            || REACTIONS.iter().any(|reaction| {
+                reaction.a == voxel.material
+                    && get_neighbors(voxels, pos).any(|vox| vox.material == reaction.b)
+            }))
+}
+
+// Compute the next reaction step of `voxels`, where the reactive voxels at `cells` and their
+// neighbors react. Each voxel only decides its own next state, based on the previous grid, so the
+// result doesn't depend on the order the grid is visited in. Voxels that turn into another
+// material take on that material's temperature. Returns whether anything changed.
+fn step_reactions(
+    voxels: &mut VoxelGrid,
+    temps: &mut TempGrid,
+    cells: &[VoxPos],
+    seed: u64,
+) -> bool {
+    let prev = &*voxels;
+    let reacted = par_flat_map(cells, |pos| {
+        let voxels = prev;
+        if !is_reactive(voxels, pos) {
+            return Vec::new();
+        }
+        FACE_OFFSETS
+            .iter()
+            .filter_map(|&offset| voxels.offset(pos, offset))
+            .chain(Some(pos))
+            .map(|near| (near, react_voxel(voxels, seed, near)))
+            .filter(|&((x, y, z), voxel)| voxel != voxels[(x, y, z)])
+            .collect()
+    });
+    for &((x, y, z), voxel) in reacted.iter() {
+        if voxel.material != voxels[(x, y, z)].material {
+            temps[(x, y, z)] = voxel.props().temperature;
+        }
+        voxels[(x, y, z)] = voxel;
+    }
+    !reacted.is_empty()
+}
+
+// Create a temperature field where every voxel has its material's initial temperature
+pub fn make_temps(voxels: &VoxelGrid) -> TempGrid {
+    let mut temps = Grid::new(voxels.size(), ROOM_TEMP);
+    let chunks = voxels
+        .chunk_positions()
+        .filter(|&chunk| !voxels.is_blank(chunk));
+    for pos in chunks.flat_map(|chunk| voxels.chunk_cells(chunk, 0)) {
+        let temp = voxels[pos].props().temperature;
+        if temp != ROOM_TEMP {
+            temps[pos] = temp;
+        }
+    }
+    temps
+}
+
+// Compute the next temperature of the voxel at `pos`. Heat flows between neighboring voxels in
+// proportion to their temperature difference and the lower of their conductivities, and a voxel's
+// temperature changes by the heat it gains divided by its heat capacity. Heat doesn't flow out of
+// the voxel grid, but air slowly returns to room temperature, burning voxels are kept at least as
+// hot as a flame, and materials like freezers stay at their initial temperature.
+fn heat_voxel(voxels: &VoxelGrid, temps: &TempGrid, pos: VoxPos) -> f32 {
+    let (x, y, z) = pos;
+    let voxel = voxels[(x, y, z)];
+    if voxel.props().holds_temperature {
+        return voxel.props().temperature;
+    }
+    let temp = temps[(x, y, z)];
+    let heat: f32 = FACE_OFFSETS
+        .iter()
+        .filter_map(|&offset| voxels.offset(pos, offset))
+        .map(|(nx, ny, nz)| {
+            let conductivity = voxel
+                .props()
+                .conductivity
+                .min(voxels[(nx, ny, nz)].props().conductivity);
+            conductivity / FACE_OFFSETS.len() as f32 * (temps[(nx, ny, nz)] - temp)
+        })
+        .sum();
+    let mut temp = temp + heat / voxel.props().heat_capacity;
+    if voxel.is_air() {
+        temp += (ROOM_TEMP - temp) * AIR_COOLING;
+        if (temp - ROOM_TEMP).abs() < AIR_SNAP {
+            temp = ROOM_TEMP;
+        }
+    }
+    if voxel.is_burning() {
+        temp = temp.max(FLAME_TEMP);
+    }
+    temp
+}
+
+// Compute the next heat diffusion step of the voxels at `cells`. Voxels at room temperature with
+// neighbors at room temperature don't change, so only voxels that are hot, cold or burning and
+// their neighbors are visited. Each voxel's next temperature only depends on the previous field,
+// so the result is deterministic.
+fn step_heat(voxels: &VoxelGrid, temps: &mut TempGrid, cells: &[VoxPos]) {
+    let prev = &*temps;
+    let heated = par_flat_map(cells, |pos| {
+        let temps = prev;
+        let (x, y, z) = pos;
+        if temps[(x, y, z)] == ROOM_TEMP && !voxels[(x, y, z)].is_burning() {
+            return Vec::new();
+        }
+        FACE_OFFSETS
+            .iter()
+            .filter_map(|&offset| voxels.offset(pos, offset))
+            .chain(Some(pos))
+            .map(|near| (near, heat_voxel(voxels, temps, near)))
+            .collect()
+    });
+    for ((x, y, z), temp) in heated {
+        temps[(x, y, z)] = temp;
+    }
+}
+
+// Get what a voxel turns into at a temperature, which could be melting, boiling, freezing,
+// condensing or catching fire. Returns `None` if the voxel doesn't change.
+fn get_phase_change(voxel: Voxel, temp: f32) -> Option<Voxel> {
+    let props = voxel.props();
+    match (props.heats_into, props.cools_into, props.ignition_point) {
+        (Some((point, material)), _, _) if temp > point => Some(Voxel::new(material, voxel.shade)),
+        (_, Some((point, material)), _) if temp < point => Some(Voxel::new(material, voxel.shade)),
+        (_, _, Some(point)) if temp > point && !voxel.burning => Some(Voxel {
+            burning: true,
+            ..voxel
+        }),
+        _ => None,
+    }
+}
+
+// Change the phase of the voxels at `cells` that got hot or cold enough. Voxels keep their
+// temperature when they change phase. Returns whether anything changed.
+fn step_phases(voxels: &mut VoxelGrid, temps: &TempGrid, cells: &[VoxPos]) -> bool {
+    let mut changed = false;
+    for &(x, y, z) in cells {
+        // Every material is stable at room temperature
+        if temps[(x, y, z)] == ROOM_TEMP {
+            continue;
+        }
+        if let Some(voxel) = get_phase_change(voxels[(x, y, z)], temps[(x, y, z)]) {
+            voxels[(x, y, z)] = voxel;
+            changed = true;
+        }
+    }
+    changed
+}
+
+// Determine if a voxel rests its weight on the voxel under it. Solids hold themselves up, and gases
+// don't weigh anything.
+fn is_loose(voxel: Voxel) -> bool {
+    match voxel.props().state {
+        MaterialState::Powder | MaterialState::Liquid => true,
+        MaterialState::Solid | MaterialState::Gas => false,
+    }
+}
+
+// Compute the weight of the column of loose voxels resting on each voxel, in kg/m^2. Only the
+// columns containing `cells` are weighed, and the rest are left at zero.
+fn get_column_loads(voxels: &VoxelGrid, cells: &[VoxPos]) -> PressureGrid {
+    let (sx, sy, sz) = voxels.size();
+    let mut loads = Grid::new(voxels.size(), 0.0);
+    let mut weighed = Grid::new((sx, 1, sz), false);
+    for &(x, _, z) in cells {
+        if weighed[(x, 0, z)] {
+            continue;
+        }
+        weighed[(x, 0, z)] = true;
+        let mut load = 0.0;
+        for y in (0..sy).rev() {
+            // Writing a zero would store the chunk for nothing, as the grid is already zero there
+            if load > 0.0 {
+                loads[(x, y, z)] = load;
+            }
+            let voxel = voxels[(x, y, z)];
+            load = if is_loose(voxel) {
+                load + voxel.props().density
+            } else {
+                0.0
+            };
+        }
+    }
+    loads
+}
+
+// Determine if a liquid voxel rests on something, rather than falling
+fn is_supported(voxels: &VoxelGrid, (x, y, z): VoxPos) -> bool {
+    y == 0 || !can_displace(voxels[(x, y, z)], voxels[(x, y - 1, z)])
+}
+
+// Determine if a liquid voxel is on the surface of its liquid, with something it can displace
+// above it
+fn is_surface(voxels: &VoxelGrid, (x, y, z): VoxPos) -> bool {
+    voxels.contains((x, y + 1, z)) && can_displace(voxels[(x, y, z)], voxels[(x, y + 1, z)])
+}
+
+// Get the connected bodies of resting liquid that include any of `cells`. Each body is made of
+// voxels of the same liquid that share faces, in the order they were found, and can reach beyond
+// `cells`.
+fn get_liquid_bodies(voxels: &VoxelGrid, cells: &[VoxPos]) -> Vec<Vec<VoxPos>> {
+    let mut visited = Grid::new(voxels.size(), false);
+    let mut bodies = Vec::new();
+    for &start in cells {
+        let (x, y, z) = start;
+        let material = voxels[(x, y, z)].material;
+        if visited[(x, y, z)]
+            || material.props().state != MaterialState::Liquid
+            || !is_supported(voxels, start)
+        {
+            continue;
+        }
+        visited[(x, y, z)] = true;
+        let mut body = vec![start];
+        let mut i = 0;
+        while i < body.len() {
+            let pos = body[i];
+            i += 1;
+            for (nx, ny, nz) in FACE_OFFSETS
+                .iter()
+                .filter_map(|&offset| voxels.offset(pos, offset))
             {
-                // Try moving sideways-down
-                let (dx, dz) = get_rand_dir(&mut state.rng);
-                let x_alt = (x as i32 + dx) as usize;
-                let z_alt = (z as i32 + dz) as usize;
-                if x_alt < VOX_MAX_X
-                    && z_alt < VOX_MAX_Z
-                    && state.voxels[x_alt][y - 1][z_alt].is_air()
+                if !visited[(nx, ny, nz)]
+                    && voxels[(nx, ny, nz)].material == material
+                    && is_supported(voxels, (nx, ny, nz))
                 {
-                    state.voxels[x][y][z] = Voxel::Air;
-                    state.voxels[x_alt][y - 1][z_alt] = hi;
-                    state.dirty = true;
+                    visited[(nx, ny, nz)] = true;
+                    body.push((nx, ny, nz));
                 }
             }
         }
+        bodies.push(body);
     }
+    bodies
 }
 
-// Calculate the forward vector based on the player angle
-pub fn compute_forward_vector(angle: Vector2<f32>) -> Vector3<f32> {
-    // The initial vector is rotated on each axis individually, because doing both rotations at
-    // once causes issues.
-    // TODO: Find a better way to do this
-    Quaternion::from(Euler {
-        x: Rad(0.0),
-        y: Rad(angle.x),
-        z: Rad(0.0),
-    })
-    .rotate_vector(
-        Quaternion::from(Euler {
-            x: Rad(angle.y),
-            y: Rad(0.0),
-            z: Rad(0.0),
+// Get the highest and lowest surface voxels of a liquid body, or `None` if the body has no
+// surface. Ties are broken by position, so the result doesn't depend on the search order.
+fn get_surface_ends(voxels: &VoxelGrid, body: &[VoxPos]) -> Option<(VoxPos, VoxPos)> {
+    let surface: Vec<VoxPos> = body
+        .iter()
+        .cloned()
+        .filter(|&pos| is_surface(voxels, pos))
+        .collect();
+    let top = surface
+        .iter()
+        .cloned()
+        .min_by_key(|&(x, y, z)| (Reverse(y), x, z))?;
+    let bottom = surface.iter().cloned().min_by_key(|&(x, y, z)| (y, x, z))?;
+    Some((top, bottom))
+}
+
+// Compute the pressure on the voxels at `cells`. Powders and liquids carry the weight of the column
+// of loose voxels above them. Liquid is also pushed on by the rest of its body, as much as its depth
+// below the body's highest surface. Returns the pressures, along with moves that carry liquid from
+// the highest surface of each body to its lowest surface, which is how liquid poured
+// into one arm of a U-tube rises in the other.
+fn get_pressures(voxels: &VoxelGrid, cells: &[VoxPos], seed: u64) -> (PressureGrid, Vec<SandMove>) {
+    let mut pressures = get_column_loads(voxels, cells);
+    let mut moves = Vec::new();
+    for body in get_liquid_bodies(voxels, cells) {
+        let (bx, by, bz) = body[0];
+        let voxel = voxels[(bx, by, bz)];
+        let density = voxel.props().density;
+        let (top, bottom) = match get_surface_ends(voxels, &body) {
+            Some(ends) => ends,
+            // Liquid sealed in a container only carries the weight above it
+            None => continue,
+        };
+        let (_, height, _) = top;
+        for &(x, y, z) in body.iter() {
+            // Liquid sealed in above the highest surface isn't pushed on by the rest of its body
+            let depth = (height as f32 - y as f32).max(0.0);
+            pressures[(x, y, z)] = pressures[(x, y, z)].max(depth * density);
+        }
+        // Moving liquid from one surface to another only helps if the surfaces are more than a
+        // voxel apart, and only the body's own depth pushes it, not what's resting on the surface
+        let (bx, by, bz) = bottom;
+        if is_pressurized(voxel, (height - by) as f32 * density - density) {
+            moves.push(SandMove {
+                kind: MoveKind::Push,
+                rank: hash_pos(seed, top),
+                src: top,
+                dst: (bx, by + 1, bz),
+            });
+        }
+    }
+    (pressures, moves)
+}
+
+// Compact the powders at `cells` that are under enough pressure, such as sand at the bottom of a
+// deep pile turning into sandstone. Returns whether anything changed.
+fn step_compaction(voxels: &mut VoxelGrid, pressures: &PressureGrid, cells: &[VoxPos]) -> bool {
+    let mut changed = false;
+    for &(x, y, z) in cells {
+        let voxel = voxels[(x, y, z)];
+        if let Some((pressure, material)) = voxel.props().compacts_into {
+            if pressures[(x, y, z)] > pressure {
+                voxels[(x, y, z)] = Voxel::new(material, voxel.shade);
+                changed = true;
+            }
+        }
+    }
+    changed
+}
+
+// An explosion centered on a voxel
+#[derive(Copy, Clone, Debug)]
+struct Blast {
+    center: VoxPos,
+    radius: f32, // In voxels
+}
+
+// Get the blasts of the explosives at `cells` that are burning. Explosives go off the tick after
+// they catch fire.
+fn get_blasts(voxels: &VoxelGrid, cells: &[VoxPos]) -> Vec<Blast> {
+    cells
+        .iter()
+        .cloned()
+        .filter(|&(x, y, z)| voxels[(x, y, z)].burning)
+        .map(|(x, y, z)| Blast {
+            center: (x, y, z),
+            radius: voxels[(x, y, z)].props().blast_radius,
         })
-        .rotate_vector(Vector3::new(0.0, 0.0, -1.0)),
-    )
+        .filter(|blast| blast.radius > 0.0)
+        .collect()
 }
 
-// Compute the (forward, right, up) vectors for the player angle
-pub fn compute_dir_vectors(angle: Vector2<f32>) -> (Vector3<f32>, Vector3<f32>, Vector3<f32>) {
-    let forward = compute_forward_vector(angle);
-    let right = forward.cross(Vector3::new(0.0, 1.0, 0.0));
-    let up = right.cross(forward);
-    (forward, right, up)
+// Get the position of the center of a voxel
+fn get_voxel_center((x, y, z): VoxPos) -> Point3<f32> {
+    Point3::new(x as f32 + 0.5, y as f32 + 0.5, z as f32 + 0.5)
 }
 
-// Get the speed of (lateral, upward) movement in m/s
-pub fn get_move_speeds(player_state: PlayerState) -> (f32, f32) {
-    match player_state {
-        PlayerState::Normal => (WALK_SPEED, JUMP_SPEED),
-        PlayerState::Running => (RUN_SPEED, JUMP_SPEED),
-        PlayerState::Flying => (FLY_SPEED, FLY_SPEED),
+// Get the voxel containing a point, returning `None` if the point is outside a voxel grid of `size`
+fn get_containing_voxel((sx, sy, sz): VoxPos, pos: Point3<f32>) -> Option<VoxPos> {
+    let in_bounds = |c: f32, max: usize| c >= 0.0 && c < max as f32;
+    if in_bounds(pos.x, sx) && in_bounds(pos.y, sy) && in_bounds(pos.z, sz) {
+        Some((pos.x as usize, pos.y as usize, pos.z as usize))
+    } else {
+        None
     }
 }
 
-// Toggle flying (used when tab key is pressed)
-pub fn toggle_flight(state: &mut GameState) {
-    // TODO: Reset run timer
-    state.player.state = match state.player.state {
-        PlayerState::Normal | PlayerState::Running => PlayerState::Flying,
-        PlayerState::Flying => PlayerState::Normal,
+// Compute the strength a blast has left when it reaches the voxel at `pos`, in voxels of blast
+// radius. The blast weakens with distance, and with the hardness of every voxel it passes through
+// on a straight line from its center, including the voxel at `pos`. Hard materials shelter what's
+// behind them.
+fn get_blast_strength(voxels: &VoxelGrid, blast: Blast, pos: VoxPos) -> f32 {
+    let start = get_voxel_center(blast.center);
+    let offset = get_voxel_center(pos) - start;
+    let distance = offset.magnitude();
+    let mut strength = blast.radius - distance;
+    // Sample the line every half voxel, counting each voxel it passes through once
+    let steps = (distance * 2.0).ceil() as usize;
+    let mut prev = blast.center;
+    for i in 1..=steps {
+        let (x, y, z) =
+            match get_containing_voxel(voxels.size(), start + offset * (i as f32 / steps as f32)) {
+                Some(cell) => cell,
+                None => continue,
+            };
+        if (x, y, z) != prev {
+            strength -= voxels[(x, y, z)].props().hardness;
+            prev = (x, y, z);
+        }
+    }
+    strength
+}
+
+// Set off a blast. Voxels that the blast reaches with strength to spare are destroyed, except that
+// loose voxels are thrown outwards as particles, and explosives are set alight so they go off next
+// tick. The exploding voxel turns into a flame. The chunks the blast reaches are woken in `awake`.
+fn apply_blast(
+    voxels: &mut VoxelGrid,
+    temps: &mut TempGrid,
+    particles: &mut Vec<Particle>,
+    awake: &mut AwakeSet,
+    blast: Blast,
+    seed: u64,
+) {
+    let (cx, cy, cz) = blast.center;
+    let (sx, sy, sz) = voxels.size();
+    let reach = blast.radius.ceil() as usize;
+    let range = |c: usize, max: usize| c.saturating_sub(reach)..(c + reach + 1).min(max);
+    // Wake one voxel further out, and everything below, since the blast can knock the ground out
+    // from under voxels
+    wake_box(
+        awake,
+        voxels.size(),
+        cx.saturating_sub(reach + 1)..cx + reach + 2,
+        0..cy + reach + 2,
+        cz.saturating_sub(reach + 1)..cz + reach + 2,
+    );
+    // Measure every hit before destroying anything, so the order voxels are visited in doesn't
+    // matter
+    let hits: Vec<(VoxPos, f32)> = iter_3d(range(cx, sx), range(cy, sy), range(cz, sz))
+        .filter(|&pos| pos != blast.center)
+        .map(|pos| (pos, get_blast_strength(voxels, blast, pos)))
+        .filter(|&(_, strength)| strength > 0.0)
+        .collect();
+    for (pos, strength) in hits {
+        let (x, y, z) = pos;
+        let voxel = voxels[(x, y, z)];
+        if voxel.is_air() {
+            continue;
+        }
+        if voxel.props().blast_radius > 0.0 {
+            voxels[(x, y, z)].burning = true;
+            continue;
+        }
+        if is_loose(voxel) {
+            let mut rng = XorShiftRng::seed_from_u64(hash_pos(seed, pos));
+            let dir = (get_voxel_center(pos) - get_voxel_center(blast.center)).normalize();
+            // Scatter the particles a little, and throw them upwards more than downwards
+            let scatter = Vector3::new(
+                rng.gen_range(-0.3, 0.3),
+                rng.gen_range(0.0, 0.6),
+                rng.gen_range(-0.3, 0.3),
+            );
+            particles.push(Particle {
+                voxel,
+                pos: get_voxel_center(pos),
+                velocity: (dir + scatter) * BLAST_SPEED * strength / blast.radius,
+            });
+        }
+        voxels[(x, y, z)] = Voxel::AIR;
+        temps[(x, y, z)] = ROOM_TEMP;
+    }
+    let mut rng = XorShiftRng::seed_from_u64(hash_pos(seed, blast.center));
+    let flame = Voxel::new(Material::Fire, rng.gen());
+    voxels[(cx, cy, cz)] = flame;
+    temps[(cx, cy, cz)] = flame.props().temperature;
+}
+
+// Put a particle that hit something back into the voxel grid, in the cell it stopped in. If that
+// cell has been filled since, the particle lands in the nearest empty cell above it instead, and
+// is lost if there's none. The chunks around where it lands are woken in `awake`.
+fn land_particle(
+    voxels: &mut VoxelGrid,
+    temps: &mut TempGrid,
+    awake: &mut AwakeSet,
+    particle: Particle,
+) {
+    if let Some((x, y, z)) = get_containing_voxel(voxels.size(), particle.pos) {
+        if let Some(y) = (y..voxels.size().1).find(|&y| voxels[(x, y, z)].is_air()) {
+            voxels[(x, y, z)] = particle.voxel;
+            temps[(x, y, z)] = particle.voxel.props().temperature;
+            wake_around(awake, voxels.size(), (x, y, z), true);
+        }
+    }
+}
+
+// Move the flying particles along their paths for one tick, under gravity. Particles land when
+// they hit a voxel or the edge of the voxel grid. Returns whether there were any particles.
+fn step_particles(
+    voxels: &mut VoxelGrid,
+    temps: &mut TempGrid,
+    particles: &mut Vec<Particle>,
+    awake: &mut AwakeSet,
+) -> bool {
+    let changed = !particles.is_empty();
+    let mut flying = Vec::new();
+    for mut particle in particles.drain(..) {
+        particle.velocity.y -= PARTICLE_GRAVITY;
+        // Move at most half a voxel at a time, so particles don't pass through voxels
+        let steps = (particle.velocity.magnitude() * 2.0).ceil().max(1.0) as usize;
+        let step = particle.velocity / steps as f32;
+        let mut hit = false;
+        for _ in 0..steps {
+            let next = particle.pos + step;
+            match get_containing_voxel(voxels.size(), next) {
+                Some((x, y, z)) if voxels[(x, y, z)].is_air() => particle.pos = next,
+                _ => {
+                    hit = true;
+                    break;
+                }
+            }
+        }
+        if hit {
+            land_particle(voxels, temps, awake, particle);
+        } else {
+            flying.push(particle);
+        }
+    }
+    *particles = flying;
+    changed
+}
+
+// Get the next electric charge of the voxel at `pos`. Charge spreads like in Wireworld: a charged
+// conductor starts discharging, a discharging conductor becomes neutral, and a neutral conductor
+// becomes charged when one or two of its neighbors are charged or charge it, like a battery. More
+// than two doesn't charge it, which lets conductors be laid out into logic gates. Voxels that don't
+// conduct are always neutral.
+fn get_next_charge(voxels: &VoxelGrid, pos: VoxPos) -> Charge {
+    let (x, y, z) = pos;
+    let voxel = voxels[(x, y, z)];
+    if !voxel.props().conductive {
+        return Charge::Neutral;
+    }
+    match voxel.charge {
+        Charge::Charged => Charge::Discharging,
+        Charge::Discharging => Charge::Neutral,
+        Charge::Neutral => {
+            let sources = get_neighbors(voxels, pos)
+                .filter(|vox| vox.charge == Charge::Charged || vox.props().charges)
+                .count();
+            if sources == 1 || sources == 2 {
+                Charge::Charged
+            } else {
+                Charge::Neutral
+            }
+        }
+    }
+}
+
+// Compute the next electric step of the voxels at `cells`, moving electric pulses one voxel along
+// their conductors. Voxels heat up each time they're charged, so a current can boil water. Returns
+// whether any voxel's charge changed.
+fn step_electricity(voxels: &mut VoxelGrid, temps: &mut TempGrid, cells: &[VoxPos]) -> bool {
+    let mut charges = Vec::new();
+    for &pos in cells {
+        let (x, y, z) = pos;
+        let voxel = voxels[(x, y, z)];
+        if voxel.charge == Charge::Neutral && !voxel.props().conductive {
+            continue;
+        }
+        let charge = get_next_charge(voxels, pos);
+        if charge != voxel.charge {
+            if charge == Charge::Charged {
+                temps[(x, y, z)] += ELECTRIC_HEAT / voxel.props().heat_capacity;
+            }
+            charges.push((pos, charge));
+        }
+    }
+    // Every charge is decided from the previous grid before any is changed
+    for &((x, y, z), charge) in charges.iter() {
+        voxels[(x, y, z)].charge = charge;
+    }
+    !charges.is_empty()
+}
+
+// Offsets of the cells an emitter tries to produce voxels in, in order of preference. The cell
+// below comes first, so that falling voxels have room to leave.
+const EMIT_OFFSETS: [(i32, i32, i32); 6] = [
+    (0, -1, 0),
+    (-1, 0, 0),
+    (1, 0, 0),
+    (0, 0, -1),
+    (0, 0, 1),
+    (0, 1, 0),
+];
+
+// Produce voxels from the emitters at `cells` that are due to emit on `tick`, such as water from
+// springs. Each emitter fills the first empty cell next to it in `EMIT_OFFSETS`, and emits nothing
+// if it's surrounded. Returns whether anything was emitted.
+fn step_emitters(
+    voxels: &mut VoxelGrid,
+    temps: &mut TempGrid,
+    cells: &[VoxPos],
+    tick: u64,
+    seed: u64,
+) -> bool {
+    let mut changed = false;
+    for &pos in cells {
+        let (x, y, z) = pos;
+        let material = match voxels[(x, y, z)].props().emits {
+            Some((period, material)) if tick.is_multiple_of(period) => material,
+            _ => continue,
+        };
+        let target = EMIT_OFFSETS
+            .iter()
+            .filter_map(|&offset| voxels.offset(pos, offset))
+            .find(|&(tx, ty, tz)| voxels[(tx, ty, tz)].is_air());
+        if let Some((tx, ty, tz)) = target {
+            let mut rng = XorShiftRng::seed_from_u64(hash_pos(seed, pos));
+            let voxel = Voxel::new(material, rng.gen());
+            voxels[(tx, ty, tz)] = voxel;
+            temps[(tx, ty, tz)] = voxel.props().temperature;
+            changed = true;
+        }
+    }
+    changed
+}
+
+// Wake every chunk of `voxels` that holds something, so that all of it gets simulated. Chunks of
+// nothing but air can't change until something next to them wakes them.
+pub fn make_awake(voxels: &VoxelGrid) -> AwakeSet {
+    voxels.stored_chunks().collect()
+}
+
+// Wake the chunks overlapping a box of voxels, ignoring the parts of the box outside a voxel grid
+// of `size`
+fn wake_box(
+    awake: &mut AwakeSet,
+    (sx, sy, sz): VoxPos,
+    xs: Range<usize>,
+    ys: Range<usize>,
+    zs: Range<usize>,
+) {
+    let range =
+        |r: Range<usize>, max: usize| r.start / CHUNK_SIZE..r.end.min(max).div_ceil(CHUNK_SIZE);
+    awake.extend(iter_3d(range(xs, sx), range(ys, sy), range(zs, sz)));
+}
+
+// Wake the chunks that a change to the voxel at `pos` could unsettle, which are the chunks it and
+// its neighbors are in. If the change moved weight around, the chunks below are woken as well,
+// since the load on the voxels under it changed.
+fn wake_around(awake: &mut AwakeSet, size: VoxPos, (x, y, z): VoxPos, weight_changed: bool) {
+    let y_min = if weight_changed {
+        0
+    } else {
+        y.saturating_sub(1)
+    };
+    wake_box(
+        awake,
+        size,
+        x.saturating_sub(1)..x + 2,
+        y_min..y + 2,
+        z.saturating_sub(1)..z + 2,
+    );
+}
+
+// Get the chunks that are simulated, which are the awake chunks that are loaded. Unloaded chunks
+// stay frozen, awake or not, until the player comes back for them.
+fn get_active_chunks<'a>(
+    voxels: &'a VoxelGrid,
+    awake: &'a AwakeSet,
+) -> impl Iterator<Item = VoxPos> + 'a {
+    awake
+        .iter()
+        .cloned()
+        .filter(move |&chunk| voxels.is_loaded(chunk))
+}
+
+// Get the voxels in the active chunks, chunk by chunk
+fn get_awake_cells(voxels: &VoxelGrid, awake: &AwakeSet) -> Vec<VoxPos> {
+    get_active_chunks(voxels, awake)
+        .flat_map(|chunk| voxels.chunk_cells(chunk, 0))
+        .collect()
+}
+
+// Run `f` on each of `cells` on a pool of threads, handing each thread a chunk of cells at a time,
+// and collect everything `f` returns in the order of `cells`. `f` only reads the previous grid and
+// returns what it wants to change instead of writing it, so the threads never race each other and
+// the result is the same however the work is scheduled.
+fn par_flat_map<T, I, F>(cells: &[VoxPos], f: F) -> Vec<T>
+where
+    T: Send,
+    I: IntoIterator<Item = T>,
+    F: Fn(VoxPos) -> I + Sync,
+{
+    let chunks: Vec<Vec<T>> = cells
+        .par_chunks(CHUNK_VOLUME)
+        .map(|chunk| chunk.iter().flat_map(|&pos| f(pos)).collect())
+        .collect();
+    chunks.into_iter().flatten().collect()
+}
+
+// Wake the chunks holding the highest and lowest surfaces of every liquid body that reaches into
+// an awake chunk. Pressure carries a change across the whole body, such as liquid poured into one
+// arm of a U-tube rising in the other, but liquid only moves at those surfaces, so the rest of the
+// body can sleep.
+fn wake_liquid_bodies(voxels: &VoxelGrid, awake: &mut AwakeSet) {
+    for body in get_liquid_bodies(voxels, &get_awake_cells(voxels, awake)) {
+        if let Some((top, bottom)) = get_surface_ends(voxels, &body) {
+            for &(x, y, z) in [top, bottom].iter() {
+                awake.insert((x / CHUNK_SIZE, y / CHUNK_SIZE, z / CHUNK_SIZE));
+            }
+        }
+    }
+}
+
+// Determine if the voxel at `pos` keeps its chunk awake even if it didn't change, because it could
+// still move, react, carry a fading electric pulse or emit voxels
+fn is_restless(voxels: &VoxelGrid, pressures: &PressureGrid, pos: VoxPos) -> bool {
+    let (x, y, z) = pos;
+    let voxel = voxels[(x, y, z)];
+    voxel.charge != Charge::Neutral
+        || voxel.props().emits.is_some()
+        || is_reactive(voxels, pos)
+        || is_unsettled(voxels, pressures, pos)
+}
+
+// Take a snapshot of the voxels and temperatures in the chunks at `chunks` and next to them.
+// Besides blasts, particles and moves, which wake the chunks they reach themselves, changes only
+// happen in active chunks and next to them, so only those need to be compared after a tick.
+fn snapshot_chunks(
+    voxels: &VoxelGrid,
+    temps: &TempGrid,
+    chunks: &[VoxPos],
+) -> Vec<(VoxPos, Voxel, f32)> {
+    chunks
+        .iter()
+        .flat_map(|&chunk| voxels.chunk_cells(chunk, 1))
+        .map(|(x, y, z)| ((x, y, z), voxels[(x, y, z)], temps[(x, y, z)]))
+        .collect()
+}
+
+// Wake the chunks around every voxel that changed since the snapshot `prev` was taken
+fn wake_changes(
+    voxels: &VoxelGrid,
+    temps: &TempGrid,
+    prev: &[(VoxPos, Voxel, f32)],
+    awake: &mut AwakeSet,
+) {
+    for &(pos, prev_voxel, prev_temp) in prev {
+        let (x, y, z) = pos;
+        let voxel = voxels[(x, y, z)];
+        if voxel != prev_voxel || temps[(x, y, z)] != prev_temp {
+            wake_around(
+                awake,
+                voxels.size(),
+                pos,
+                is_loose(voxel) || is_loose(prev_voxel),
+            );
+        }
+    }
+}
+
+// Advance the voxel simulation by one tick, where `tick` counts the ticks run so far. Burning
+// explosives go off, flying particles move, emitters produce voxels, electric pulses move, the
+// voxels react with their neighbors, powders under pressure compact, the voxels move, and then heat
+// spreads and changes the phase of voxels. Reacting before moving lets voxels react with whatever
+// they were placed next to, such as a spark dropped on oil. Only the voxels in awake, loaded chunks
+// are simulated, and `awake` is updated to the chunks to simulate next tick: those where something
+// changed or could still change. The costly per-voxel work is spread over threads a chunk at a
+// time, without changing the result. Returns whether any voxel changed, along with the blasts that
+// went off.
+fn step_voxels(
+    voxels: &mut VoxelGrid,
+    temps: &mut TempGrid,
+    particles: &mut Vec<Particle>,
+    awake: &mut AwakeSet,
+    tick: u64,
+    seed: u64,
+) -> (bool, Vec<Blast>) {
+    let mut rng = XorShiftRng::seed_from_u64(seed);
+    wake_liquid_bodies(voxels, awake);
+    let active: Vec<VoxPos> = get_active_chunks(voxels, awake).collect();
+    let cells = get_awake_cells(voxels, awake);
+    let prev = snapshot_chunks(voxels, temps, &active);
+    // Unloaded chunks stay as awake or asleep as they were until they're loaded again
+    for chunk in active.iter() {
+        awake.remove(chunk);
+    }
+    let blasts = get_blasts(voxels, &cells);
+    for &blast in blasts.iter() {
+        apply_blast(voxels, temps, particles, awake, blast, rng.gen());
+    }
+    let mut changed = !blasts.is_empty();
+    changed |= step_particles(voxels, temps, particles, awake);
+    changed |= step_emitters(voxels, temps, &cells, tick, rng.gen());
+    changed |= step_electricity(voxels, temps, &cells);
+    changed |= step_reactions(voxels, temps, &cells, rng.gen());
+    let loads = get_column_loads(voxels, &cells);
+    changed |= step_compaction(voxels, &loads, &cells);
+    let (moves, pressures) = get_sand_moves(voxels, &cells, rng.gen());
+    if !moves.is_empty() {
+        apply_moves(voxels, &moves);
+        apply_moves(temps, &moves);
+        changed = true;
+    }
+    // Liquid pushed between the surfaces of a body can land far from where it started
+    for &SandMove { src, dst, .. } in moves.iter() {
+        let (x, y, z) = dst;
+        let loose = is_loose(voxels[(x, y, z)]);
+        wake_around(awake, voxels.size(), src, loose);
+        wake_around(awake, voxels.size(), dst, loose);
+    }
+    step_heat(voxels, temps, &cells);
+    changed |= step_phases(voxels, temps, &cells);
+    wake_changes(voxels, temps, &prev, awake);
+    let restless = par_flat_map(&cells, |pos| {
+        Some(pos).filter(|&pos| is_restless(voxels, &pressures, pos))
+    });
+    for pos in restless {
+        wake_around(awake, voxels.size(), pos, false);
+    }
+    (changed, blasts)
+}
+
+// Push the player away from a blast, harder the closer they are to it. The push is tilted upwards
+// so that it lifts the player off the ground, and the player can't steer until they land.
+fn knock_back(player: &mut Player, blast: Blast) {
+    let body = player.pos - Vector3::new(0.0, EYE_HEIGHT / 2.0, 0.0);
+    let offset = body - get_voxel_center(blast.center);
+    let distance = offset.magnitude();
+    let reach = blast.radius * KNOCKBACK_REACH;
+    if distance < reach {
+        let away = if distance > 0.0 {
+            offset / distance
+        } else {
+            Vector3::zero()
+        };
+        // Tilting a push straight down cancels it out, so a player right under the blast is
+        // pushed straight up instead
+        let tilted = away + Vector3::unit_y();
+        let dir = if tilted.magnitude2() > 1e-6 {
+            tilted.normalize()
+        } else {
+            Vector3::unit_y()
+        };
+        player.velocity += dir * KNOCKBACK_SPEED * (1.0 - distance / reach);
+        player.knocked_back = true;
+    }
+}
+
+// Propagate the voxels downwards (gravity), spread heat, pressure and electricity, let the voxels
+// react with each other and set off explosives, knocking back the player
+fn do_tick(state: &mut GameState) {
+    let seed = state.rng.gen();
+    let (changed, blasts) = step_voxels(
+        &mut state.voxels,
+        &mut state.temps,
+        &mut state.particles,
+        &mut state.awake,
+        state.tick,
+        seed,
+    );
+    state.tick += 1;
+    if changed {
+        state.dirty = true;
+    }
+    for blast in blasts {
+        knock_back(&mut state.player, blast);
+    }
+}
+
+// Advance the tick progress by one physics step at the simulation speed `speed`, returning how
+// many ticks are now due
+fn get_due_ticks(progress: &mut u32, speed: usize) -> u32 {
+    *progress += SIM_SPEEDS[speed];
+    let due = *progress / TICK_PROGRESS;
+    *progress %= TICK_PROGRESS;
+    due
+}
+
+// Run the voxel simulation for one physics step. The simulation ticks at a rate set by the
+// simulation speed, or only when single-stepped while the sand is paused.
+pub fn do_sandfall(state: &mut GameState) {
+    let due = if state.sand_paused {
+        state.pending_ticks
+    } else {
+        get_due_ticks(&mut state.tick_progress, state.sim_speed)
+    };
+    state.pending_ticks = 0;
+    for _ in 0..due {
+        do_tick(state);
+    }
+}
+
+// Load the chunks within `LOAD_DISTANCE` chunks of the chunk `center` and unload the rest, in both
+// the voxel grid and the temperature field. Unloaded chunks are packed away and stay frozen until
+// they're loaded again.
+fn load_chunks_around(voxels: &mut VoxelGrid, temps: &mut TempGrid, (cx, cy, cz): VoxPos) {
+    let (nx, ny, nz) = voxels.chunk_counts();
+    let near =
+        |c: usize, max: usize| c.saturating_sub(LOAD_DISTANCE)..(c + LOAD_DISTANCE + 1).min(max);
+    let chunks: Vec<VoxPos> = iter_3d(near(cx, nx), near(cy, ny), near(cz, nz)).collect();
+    voxels.load_only(chunks.iter().cloned());
+    temps.load_only(chunks);
+}
+
+// Load the chunks around the player whenever they move into another chunk
+pub fn load_around_player(state: &mut GameState) {
+    let (sx, sy, sz) = state.voxels.size();
+    let pos = state.player.pos;
+    let chunk_of = |c: f32, max: usize| clamp(0.0, c, (max - 1) as f32) as usize / CHUNK_SIZE;
+    let center = (
+        chunk_of(pos.x, sx),
+        chunk_of(pos.y, sy),
+        chunk_of(pos.z, sz),
+    );
+    if state.loaded_around != Some(center) {
+        load_chunks_around(&mut state.voxels, &mut state.temps, center);
+        state.loaded_around = Some(center);
+        state.dirty = true;
+    }
+}
+
+// Calculate the forward vector based on the player angle
+pub fn compute_forward_vector(angle: Vector2<f32>) -> Vector3<f32> {
+    // The initial vector is rotated on each axis individually, because doing both rotations at
+    // once causes issues.
+    // TODO: Find a better way to do this
+    Quaternion::from(Euler {
+        x: Rad(0.0),
+        y: Rad(angle.x),
+        z: Rad(0.0),
+    })
+    .rotate_vector(
+        Quaternion::from(Euler {
+            x: Rad(angle.y),
+            y: Rad(0.0),
+            z: Rad(0.0),
+        })
+        .rotate_vector(Vector3::new(0.0, 0.0, -1.0)),
+    )
+}
+
+// Compute the (forward, right, up) vectors for the player angle
+pub fn compute_dir_vectors(angle: Vector2<f32>) -> (Vector3<f32>, Vector3<f32>, Vector3<f32>) {
+    let forward = compute_forward_vector(angle);
+    let right = forward.cross(Vector3::new(0.0, 1.0, 0.0));
+    let up = right.cross(forward);
+    (forward, right, up)
+}
+
+// Get the speed of (lateral, upward) movement in m/s
+pub fn get_move_speeds(player_state: PlayerState) -> (f32, f32) {
+    match player_state {
+        PlayerState::Normal => (WALK_SPEED, JUMP_SPEED),
+        PlayerState::Running => (RUN_SPEED, JUMP_SPEED),
+        PlayerState::Flying => (FLY_SPEED, FLY_SPEED),
+    }
+}
+
+// Toggle flying (used when tab key is pressed)
+pub fn toggle_flight(state: &mut GameState) {
+    // TODO: Reset run timer
+    state.player.state = match state.player.state {
+        PlayerState::Normal | PlayerState::Running => PlayerState::Flying,
+        PlayerState::Flying => PlayerState::Normal,
+    }
+}
+
+// Freeze/unfreeze the voxel simulation without stopping the player
+pub fn toggle_sand_pause(state: &mut GameState) {
+    state.sand_paused = !state.sand_paused;
+    state.pending_ticks = 0;
+}
+
+// Run a single voxel simulation tick on the next physics step, pausing the sand if it's running
+pub fn step_sand(state: &mut GameState) {
+    state.sand_paused = true;
+    state.pending_ticks += 1;
+}
+
+// Print what's needed to check that another run reached the same world: the seed, the number of
+// ticks run and a hash of the voxel grid
+pub fn print_world_hash(state: &GameState) {
+    println!(
+        "Seed {}, tick {}, world hash {:016x}",
+        state.seed,
+        state.tick,
+        state.voxels.state_hash()
+    );
+}
+
+// Double or halve the simulation speed, within the range of `SIM_SPEEDS`
+pub fn change_sim_speed(state: &mut GameState, faster: bool) {
+    state.sim_speed = if faster {
+        (state.sim_speed + 1).min(SIM_SPEEDS.len() - 1)
+    } else {
+        state.sim_speed.saturating_sub(1)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use client::{self, VoxelShade};
+    use world::DEFAULT_WORLD_SIZE;
+
+    fn make_empty_world() -> VoxelGrid {
+        client::make_empty_world(DEFAULT_WORLD_SIZE)
+    }
+
+    fn sand(shade: VoxelShade) -> Voxel {
+        Voxel::new(Material::Sand, shade)
+    }
+
+    fn water() -> Voxel {
+        Voxel::new(Material::Water, 0)
+    }
+
+    fn oil() -> Voxel {
+        Voxel::new(Material::Oil, 0)
+    }
+
+    fn smoke() -> Voxel {
+        Voxel::new(Material::Smoke, 0)
+    }
+
+    fn fire() -> Voxel {
+        Voxel::new(Material::Fire, 0)
+    }
+
+    fn wood() -> Voxel {
+        Voxel::new(Material::Wood, 0)
+    }
+
+    fn lava() -> Voxel {
+        Voxel::new(Material::Lava, 0)
+    }
+
+    // A stick of TNT that's already been lit
+    fn lit_tnt() -> Voxel {
+        Voxel {
+            burning: true,
+            ..Voxel::new(Material::Tnt, 0)
+        }
+    }
+
+    // Step the reactions between `a` and `b` placed next to each other until something changes,
+    // returning what they turned into
+    fn react_pair(a: Voxel, b: Voxel) -> (Voxel, Voxel) {
+        let mut voxels = make_empty_world();
+        voxels[(5, 5, 5)] = a;
+        voxels[(6, 5, 5)] = b;
+        let mut temps = make_temps(&voxels);
+        let next = (0..1000)
+            .find_map(|seed| {
+                let mut next = voxels.clone();
+                if step_reactions(&mut next, &mut temps, &all_cells(), seed) {
+                    Some(next)
+                } else {
+                    None
+                }
+            })
+            .unwrap();
+        (next[(5, 5, 5)], next[(6, 5, 5)])
+    }
+
+    // Count the voxels that are on fire
+    fn count_burning(voxels: &VoxelGrid) -> usize {
+        voxels
+            .positions()
+            .filter(|&(x, y, z)| voxels[(x, y, z)].is_burning())
+            .count()
+    }
+
+    // Build a closed vertical shaft of walls around the column at `(x, z)`, with the inside
+    // spanning from the floor to `height`. Returns the cells inside the shaft, from the bottom up.
+    fn make_shaft(voxels: &mut VoxelGrid, x: usize, z: usize, height: usize) -> Vec<VoxPos> {
+        for (wx, wy, wz) in iter_3d(x - 1..x + 2, 0..height + 1, z - 1..z + 2) {
+            if (wx, wz) != (x, z) || wy == height {
+                voxels[(wx, wy, wz)] = Voxel::BOUNDARY;
+            }
+        }
+        (0..height).map(|y| (x, y, z)).collect()
+    }
+
+    // Count the non-air voxels in the grid
+    fn count_voxels(voxels: &VoxelGrid) -> usize {
+        voxels
+            .positions()
+            .filter(|&(x, y, z)| !voxels[(x, y, z)].is_air())
+            .count()
+    }
+
+    // Get every voxel position in the grid, chunk by chunk
+    fn all_cells() -> Vec<VoxPos> {
+        let voxels = make_empty_world();
+        voxels
+            .chunk_positions()
+            .flat_map(|chunk| voxels.chunk_cells(chunk, 0))
+            .collect()
+    }
+
+    // Compute the next sandfall step of `voxels`, returning `None` if nothing moved
+    fn step_sandfall(voxels: &VoxelGrid, seed: u64) -> Option<VoxelGrid> {
+        let (moves, _) = get_sand_moves(voxels, &all_cells(), seed);
+        if moves.is_empty() {
+            None
+        } else {
+            let mut next = voxels.clone();
+            apply_moves(&mut next, &moves);
+            Some(next)
+        }
+    }
+
+    // Step the voxel simulation `steps` times, starting with every voxel at its initial
+    // temperature
+    fn run(voxels: &mut VoxelGrid, steps: u64) {
+        let mut temps = make_temps(voxels);
+        run_with_temps(voxels, &mut temps, steps);
+    }
+
+    // Step the voxel simulation `steps` times from the given temperatures
+    fn run_with_temps(voxels: &mut VoxelGrid, temps: &mut TempGrid, steps: u64) {
+        let mut awake = make_awake(voxels);
+        run_until(voxels, temps, &mut awake, steps, |_, _| false);
+    }
+
+    // Step the voxel simulation up to `steps` times from the given temperatures and awake chunks,
+    // checking `done` after each step. Returns whether `done` held before running out of steps.
+    fn run_until<F>(
+        voxels: &mut VoxelGrid,
+        temps: &mut TempGrid,
+        awake: &mut AwakeSet,
+        steps: u64,
+        mut done: F,
+    ) -> bool
+    where
+        F: FnMut(&VoxelGrid, &AwakeSet) -> bool,
+    {
+        let mut particles = Vec::new();
+        (0..steps).any(|seed| {
+            step_voxels(voxels, temps, &mut particles, awake, seed, seed);
+            done(voxels, awake)
+        })
+    }
+
+    // Step the sandfall simulation `steps` times, placing `voxel` at `source` each step if there's
+    // room. Returns the number of voxels placed.
+    fn run_with_source(voxels: &mut VoxelGrid, source: VoxPos, voxel: Voxel, steps: u64) -> usize {
+        let (sx, sy, sz) = source;
+        let mut placed = 0;
+        for seed in 0..steps {
+            if voxels[(sx, sy, sz)].is_air() {
+                voxels[(sx, sy, sz)] = voxel;
+                placed += 1;
+            }
+            if let Some(next) = step_sandfall(voxels, seed) {
+                *voxels = next;
+            }
+        }
+        placed
+    }
+
+    // Get the positions of the voxels made of `material`
+    fn find(voxels: &VoxelGrid, material: Material) -> Vec<VoxPos> {
+        voxels
+            .positions()
+            .filter(|&(x, y, z)| voxels[(x, y, z)].material == material)
+            .collect()
+    }
+
+    #[test]
+    fn grain_falls_one_cell_per_step() {
+        let mut voxels = make_empty_world();
+        voxels[(3, 10, 3)] = sand(0);
+        let next = step_sandfall(&voxels, 0).unwrap();
+        assert!(next[(3, 10, 3)].is_air());
+        assert_eq!(next[(3, 9, 3)], sand(0));
+    }
+
+    #[test]
+    fn settled_grain_stays_put() {
+        let mut voxels = make_empty_world();
+        voxels[(3, 0, 3)] = sand(0);
+        assert!(step_sandfall(&voxels, 0).is_none());
+    }
+
+    #[test]
+    fn contested_cell_goes_to_one_grain() {
+        let mut voxels = make_empty_world();
+        // Two grains resting on pillars, both wanting to slide into the gap between them
+        voxels[(2, 0, 3)] = sand(0);
+        voxels[(4, 0, 3)] = sand(0);
+        voxels[(2, 1, 3)] = sand(1);
+        voxels[(4, 1, 3)] = sand(2);
+        let moves = vec![(2, 1, 3), (4, 1, 3)]
+            .into_iter()
+            .enumerate()
+            .map(|(i, src)| SandMove {
+                kind: MoveKind::Slide,
+                rank: i as u64,
+                src,
+                dst: (3, 0, 3),
+            })
+            .collect();
+        let mut next = voxels.clone();
+        apply_moves(&mut next, &resolve_moves(moves, voxels.size()));
+        assert_eq!(next[(3, 0, 3)], sand(1));
+        assert!(next[(2, 1, 3)].is_air());
+        assert_eq!(next[(4, 1, 3)], sand(2));
+    }
+
+    #[test]
+    fn fall_beats_slide() {
+        let mut voxels = make_empty_world();
+        voxels[(2, 0, 3)] = sand(0);
+        voxels[(2, 1, 3)] = sand(1);
+        voxels[(3, 1, 3)] = sand(2);
+        let moves = vec![
+            SandMove {
+                kind: MoveKind::Slide,
+                rank: 0,
+                src: (2, 1, 3),
+                dst: (3, 0, 3),
+            },
+            SandMove {
+                kind: MoveKind::Fall,
+                rank: 1,
+                src: (3, 1, 3),
+                dst: (3, 0, 3),
+            },
+        ];
+        let mut next = voxels.clone();
+        apply_moves(&mut next, &resolve_moves(moves, voxels.size()));
+        assert_eq!(next[(3, 0, 3)], sand(2));
+        assert_eq!(next[(2, 1, 3)], sand(1));
+    }
+
+    #[test]
+    fn step_is_independent_of_move_order() {
+        let mut voxels = make_empty_world();
+        run_with_source(&mut voxels, (25, 20, 25), sand(0), 150);
+        let (pressures, _) = get_pressures(&voxels, &all_cells(), 1234);
+        let moves: Vec<SandMove> = voxels
+            .positions()
+            .filter_map(|pos| get_sand_move(&voxels, &pressures, 1234, pos))
+            .collect();
+        assert!(!moves.is_empty());
+        let mut shuffled = moves.clone();
+        shuffled.shuffle(&mut XorShiftRng::seed_from_u64(0));
+        let mut reversed = moves.clone();
+        reversed.reverse();
+        let apply = |moves| {
+            let mut next = voxels.clone();
+            apply_moves(&mut next, &resolve_moves(moves, voxels.size()));
+            next
+        };
+        let expected = apply(moves);
+        assert!(apply(shuffled) == expected);
+        assert!(apply(reversed) == expected);
+    }
+
+    #[test]
+    fn point_source_forms_symmetric_pile() {
+        let mut voxels = make_empty_world();
+        let c = 25;
+        run_with_source(&mut voxels, (c, 10, c), sand(0), 600);
+        let total = count_voxels(&voxels);
+        // Compare the amount of sand on either side of the source along each axis
+        let (mut left, mut right, mut back, mut front) = (0i32, 0i32, 0i32, 0i32);
+        for (x, y, z) in voxels.positions() {
+            if !voxels[(x, y, z)].is_air() {
+                if x < c {
+                    left += 1;
+                } else if x > c {
+                    right += 1;
+                }
+                if z < c {
+                    back += 1;
+                } else if z > c {
+                    front += 1;
+                }
+            }
+        }
+        let tolerance = total as i32 / 10;
+        assert!(total > 400);
+        assert!((left - right).abs() < tolerance);
+        assert!((back - front).abs() < tolerance);
+        // The peak of the pile is directly under the source
+        let (sx, sy, sz) = voxels.size();
+        let height = |x: usize, z: usize| (0..sy).filter(|&y| !voxels[(x, y, z)].is_air()).count();
+        assert!(iter_3d(0..sx, 0..1, 0..sz).all(|(x, _, z)| height(x, z) <= height(c, c)));
+    }
+
+    #[test]
+    fn sand_sinks_through_water() {
+        let mut voxels = make_empty_world();
+        for y in 0..5 {
+            voxels[(3, y, 3)] = water();
+        }
+        voxels[(3, 5, 3)] = sand(0);
+        let next = step_sandfall(&voxels, 0).unwrap();
+        assert_eq!(next[(3, 4, 3)], sand(0));
+        assert_eq!(next[(3, 5, 3)], water());
+        run(&mut voxels, 10);
+        assert_eq!(voxels[(3, 0, 3)], sand(0));
+    }
+
+    #[test]
+    fn water_levels_out_on_flat_ground() {
+        let mut voxels = make_empty_world();
+        for y in 0..20 {
+            voxels[(25, y, 25)] = water();
+        }
+        run(&mut voxels, 300);
+        let water = find(&voxels, Material::Water);
+        assert_eq!(water.len(), 20);
+        assert!(water.iter().all(|&(_, y, _)| y == 0));
+        // Once level, the water comes to rest
+        assert!(step_sandfall(&voxels, 300).is_none());
+    }
+
+    #[test]
+    fn water_fills_sand_basin() {
+        let mut voxels = make_empty_world();
+        // A block of sand with a pit dug into it
+        for (x, y, z) in iter_3d(15..35, 0..5, 15..35) {
+            if y == 0 || !(22..28).contains(&x) || !(22..28).contains(&z) {
+                voxels[(x, y, z)] = sand(0);
+            }
+        }
+        run(&mut voxels, 100);
+        let poured = run_with_source(&mut voxels, (25, 10, 25), water(), 100);
+        run(&mut voxels, 400);
+        let water = find(&voxels, Material::Water);
+        assert_eq!(water.len(), poured);
+        // None of the water escaped the basin
+        assert!(water
+            .iter()
+            .all(|&(x, _, z)| x > 15 && x < 34 && z > 15 && z < 34));
+        // The water surface is level, ignoring the odd voxel that's still falling after flowing
+        // out from under the surface
+        let top = water.iter().map(|&(_, y, _)| y).max().unwrap();
+        let surface: Vec<VoxPos> = water
+            .iter()
+            .cloned()
+            .filter(|&(x, y, z)| (y + 1..voxels.size().1).all(|y| voxels[(x, y, z)].is_air()))
+            .collect();
+        assert!(surface.iter().all(|&(_, y, _)| y + 1 >= top));
+    }
+
+    #[test]
+    fn layered_column_settles_in_density_order() {
+        let mut voxels = make_empty_world();
+        let shaft = make_shaft(&mut voxels, 5, 5, 10);
+        let column = [
+            smoke(),
+            oil(),
+            sand(0),
+            water(),
+            Voxel::AIR,
+            smoke(),
+            water(),
+            oil(),
+            Voxel::AIR,
+            sand(0),
+        ];
+        for (&(x, y, z), &voxel) in shaft.iter().zip(column.iter()) {
+            voxels[(x, y, z)] = voxel;
+        }
+        // Only move the voxels, so the smoke doesn't dissipate
+        for seed in 0..50 {
+            if let Some(next) = step_sandfall(&voxels, seed) {
+                voxels = next;
+            }
+        }
+        let settled: Vec<Material> = shaft
+            .iter()
+            .map(|&(x, y, z)| voxels[(x, y, z)].material)
+            .collect();
+        assert_eq!(
+            settled,
+            vec![
+                Material::Sand,
+                Material::Sand,
+                Material::Water,
+                Material::Water,
+                Material::Oil,
+                Material::Oil,
+                Material::Air,
+                Material::Air,
+                Material::Smoke,
+                Material::Smoke,
+            ]
+        );
+    }
+
+    #[test]
+    fn oil_floats_on_water() {
+        let mut voxels = make_empty_world();
+        // A tank with oil poured in first, and then water
+        for (x, y, z) in iter_3d(19..31, 0..4, 19..31) {
+            voxels[(x, y, z)] = if x == 19 || x == 30 || z == 19 || z == 30 {
+                Voxel::BOUNDARY
+            } else if y == 0 {
+                oil()
+            } else if y == 1 {
+                water()
+            } else {
+                Voxel::AIR
+            };
+        }
+        run(&mut voxels, 100);
+        let oil = find(&voxels, Material::Oil);
+        let water = find(&voxels, Material::Water);
+        let lowest_oil = oil.iter().map(|&(_, y, _)| y).min().unwrap();
+        let highest_water = water.iter().map(|&(_, y, _)| y).max().unwrap();
+        assert_eq!(oil.len(), 100);
+        assert!(lowest_oil > highest_water);
+    }
+
+    #[test]
+    fn gas_rises_through_air() {
+        let mut voxels = make_empty_world();
+        for x in 20..30 {
+            voxels[(x, 0, 25)] = smoke();
+        }
+        run(&mut voxels, 100);
+        let smoke = find(&voxels, Material::Smoke);
+        assert!(!smoke.is_empty());
+        assert!(smoke.iter().all(|&(_, y, _)| y == voxels.size().1 - 1));
+    }
+
+    #[test]
+    fn fire_spreads_through_wood_and_burns_out() {
+        let mut voxels = make_empty_world();
+        // A wooden plank with a row of flames underneath it
+        for (x, y, z) in iter_3d(20..30, 1..3, 20..30) {
+            voxels[(x, y, z)] = if y == 2 {
+                wood()
+            } else if z == 25 {
+                fire()
+            } else {
+                Voxel::AIR
+            };
+        }
+        let mut temps = make_temps(&voxels);
+        let mut awake = make_awake(&voxels);
+        let mut burning = 0;
+        run_until(&mut voxels, &mut temps, &mut awake, 100, |voxels, _| {
+            burning = burning.max(count_burning(voxels));
+            false
+        });
+        assert!(burning > 20);
+        run_with_temps(&mut voxels, &mut temps, 1000);
+        assert!(find(&voxels, Material::Wood).is_empty());
+        assert_eq!(count_burning(&voxels), 0);
+        assert!(!find(&voxels, Material::Smoke).is_empty());
+    }
+
+    #[test]
+    fn gas_drifts_sideways_as_it_rises() {
+        let mut voxels = make_empty_world();
+        voxels[(25, 0, 25)] = smoke();
+        let mut moved_sideways = false;
+        for seed in 0..20 {
+            voxels = step_sandfall(&voxels, seed).unwrap();
+            let smoke = find(&voxels, Material::Smoke);
+            moved_sideways |= smoke.iter().any(|&(x, _, z)| (x, z) != (25, 25));
+        }
+        assert!(moved_sideways);
+    }
+
+    #[test]
+    fn gas_dissipates_after_its_lifetime() {
+        let mut voxels = make_empty_world();
+        for (x, y, z) in iter_3d(0..10, 0..1, 0..10) {
+            voxels[(x * 2, y, z * 2)] = smoke();
+        }
+        let lifetime = Material::Smoke.props().lifetime as u64;
+        let mut temps = make_temps(&voxels);
+        for seed in 0..lifetime {
+            step_reactions(&mut voxels, &mut temps, &all_cells(), seed);
+        }
+        // About 1/e of the gas is left after one lifetime
+        let left = find(&voxels, Material::Smoke).len();
+        assert!(left > 20 && left < 55);
+        assert!(count_voxels(&voxels) == left);
+    }
+
+    #[test]
+    fn flame_rises_and_burns_out_into_smoke() {
+        let mut voxels = make_empty_world();
+        for x in 20..30 {
+            voxels[(x, 0, 25)] = fire();
+        }
+        run(&mut voxels, 50);
+        assert!(find(&voxels, Material::Fire).is_empty());
+        let smoke = find(&voxels, Material::Smoke);
+        assert!(!smoke.is_empty());
+        assert!(smoke.iter().all(|&(_, y, _)| y > 10));
+    }
+
+    #[test]
+    fn fire_does_not_spread_to_nonflammable_voxels() {
+        let mut voxels = make_empty_world();
+        for (x, y, z) in iter_3d(4..7, 0..3, 4..7) {
+            voxels[(x, y, z)] = if y == 1 && x == 5 && z == 5 {
+                fire()
+            } else {
+                sand(0)
+            };
+        }
+        run(&mut voxels, 100);
+        assert_eq!(find(&voxels, Material::Sand).len(), 26);
+        assert_eq!(count_burning(&voxels), 0);
+    }
+
+    #[test]
+    fn lava_and_water_make_stone_and_steam() {
+        let (a, b) = react_pair(lava(), water());
+        assert_eq!((a.material, b.material), (Material::Stone, Material::Steam));
+        // Reactions work the same in either direction
+        let (a, b) = react_pair(water(), lava());
+        assert_eq!((a.material, b.material), (Material::Steam, Material::Stone));
+    }
+
+    #[test]
+    fn lava_melts_sand_into_glass() {
+        let (a, b) = react_pair(lava(), sand(0));
+        assert_eq!((a.material, b.material), (Material::Lava, Material::Glass));
+    }
+
+    #[test]
+    fn lava_sets_wood_on_fire() {
+        let (a, b) = react_pair(lava(), wood());
+        assert_eq!((a.material, b.material), (Material::Lava, Material::Fire));
+    }
+
+    #[test]
+    fn unlisted_materials_do_not_react() {
+        let mut voxels = make_empty_world();
+        voxels[(5, 5, 5)] = lava();
+        voxels[(6, 5, 5)] = Voxel::new(Material::Stone, 0);
+        let mut temps = make_temps(&voxels);
+        assert!((0..100).all(|seed| !step_reactions(&mut voxels, &mut temps, &all_cells(), seed)));
+    }
+
+    #[test]
+    fn lava_flows_slower_than_water() {
+        let spread = |voxel: Voxel| {
+            let mut voxels = make_empty_world();
+            for y in 0..10 {
+                voxels[(25, y, 25)] = voxel;
+            }
+            for seed in 0..20 {
+                if let Some(next) = step_sandfall(&voxels, seed) {
+                    voxels = next;
+                }
+            }
+            find(&voxels, voxel.material)
+                .iter()
+                .map(|&(x, _, z)| (x as i32 - 25).abs().max((z as i32 - 25).abs()))
+                .max()
+                .unwrap()
+        };
+        assert!(spread(lava()) < spread(water()));
+    }
+
+    #[test]
+    fn heat_flows_from_hot_to_cold_and_is_conserved() {
+        let mut voxels = make_empty_world();
+        // Two stones sealed in walls, which don't conduct heat
+        for (x, y, z) in iter_3d(4..8, 4..7, 4..7) {
+            voxels[(x, y, z)] = Voxel::BOUNDARY;
+        }
+        voxels[(5, 5, 5)] = Voxel::new(Material::Stone, 0);
+        voxels[(6, 5, 5)] = Voxel::new(Material::Stone, 0);
+        let mut temps = make_temps(&voxels);
+        temps[(5, 5, 5)] = 100.0;
+        step_heat(&voxels, &mut temps, &all_cells());
+        assert!(temps[(5, 5, 5)] < 100.0);
+        assert!(temps[(6, 5, 5)] > ROOM_TEMP);
+        assert!((temps[(5, 5, 5)] + temps[(6, 5, 5)] - 120.0).abs() < 1e-3);
+        // The walls and the air around them are untouched
+        assert_eq!(temps[(4, 5, 5)], ROOM_TEMP);
+        assert_eq!(temps[(3, 5, 5)], ROOM_TEMP);
+        for _ in 0..100 {
+            step_heat(&voxels, &mut temps, &all_cells());
+        }
+        assert!((temps[(5, 5, 5)] - 60.0).abs() < 0.1);
+        assert!((temps[(6, 5, 5)] - 60.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn heat_step_is_deterministic() {
+        let simulate = || {
+            let mut voxels = make_empty_world();
+            for (x, y, z) in iter_3d(20..30, 0..3, 20..30) {
+                voxels[(x, y, z)] = if y == 0 { lava() } else { water() };
+            }
+            let mut temps = make_temps(&voxels);
+            run_with_temps(&mut voxels, &mut temps, 20);
+            (voxels, temps)
+        };
+        let (voxels_a, temps_a) = simulate();
+        let (voxels_b, temps_b) = simulate();
+        assert!(voxels_a == voxels_b);
+        assert!(temps_a
+            .positions()
+            .all(|(x, y, z)| temps_a[(x, y, z)].to_bits() == temps_b[(x, y, z)].to_bits()));
+    }
+
+    #[test]
+    fn materials_change_phase_at_their_transition_points() {
+        let phase = |material: Material, temp: f32| {
+            get_phase_change(Voxel::new(material, 0), temp).map(|voxel| voxel.material)
+        };
+        assert_eq!(phase(Material::Ice, -1.0), None);
+        assert_eq!(phase(Material::Ice, 1.0), Some(Material::Water));
+        assert_eq!(phase(Material::Water, -1.0), Some(Material::Ice));
+        assert_eq!(phase(Material::Water, ROOM_TEMP), None);
+        assert_eq!(phase(Material::Water, 101.0), Some(Material::Steam));
+        assert_eq!(phase(Material::Steam, 99.0), Some(Material::Water));
+        assert_eq!(phase(Material::Sand, 1701.0), Some(Material::Glass));
+        assert_eq!(phase(Material::Lava, 699.0), Some(Material::Stone));
+        assert_eq!(phase(Material::Stone, 1201.0), Some(Material::Lava));
+        assert_eq!(phase(Material::Glass, 5000.0), None);
+        // Flammable materials catch fire instead
+        let wood = get_phase_change(wood(), 301.0).unwrap();
+        assert_eq!(wood.material, Material::Wood);
+        assert!(wood.burning);
+        assert_eq!(get_phase_change(wood, 301.0), None);
+    }
+
+    #[test]
+    fn lava_boils_water_through_stone() {
+        let mut voxels = make_empty_world();
+        let shaft = make_shaft(&mut voxels, 5, 5, 4);
+        let column = [lava(), Voxel::new(Material::Stone, 0), water(), Voxel::AIR];
+        for (&(x, y, z), &voxel) in shaft.iter().zip(column.iter()) {
+            voxels[(x, y, z)] = voxel;
+        }
+        let mut temps = make_temps(&voxels);
+        run_with_temps(&mut voxels, &mut temps, 30);
+        assert!(find(&voxels, Material::Water).is_empty());
+        assert_eq!(find(&voxels, Material::Steam).len(), 1);
+        assert_eq!(voxels[(5, 1, 5)].material, Material::Stone);
+    }
+
+    #[test]
+    fn acid_dissolves_voxels_and_is_used_up() {
+        let mut voxels = make_empty_world();
+        let acid = Voxel::new(Material::Acid, 0);
+        voxels[(5, 5, 5)] = acid;
+        voxels[(6, 5, 5)] = sand(0);
+        let (a, b) = (0..1000)
+            .find_map(|seed| {
+                let mut next = voxels.clone();
+                if step_reactions(&mut next, &mut make_temps(&voxels), &all_cells(), seed) {
+                    Some((next[(5, 5, 5)], next[(6, 5, 5)]))
+                } else {
+                    None
+                }
+            })
+            .unwrap();
+        assert!(a.is_air() && b.is_air());
+    }
+
+    #[test]
+    fn acid_resistance_slows_dissolving() {
+        // Count how many voxels of `material` a pool of acid on top of them eats through
+        let eaten = |material: Material| {
+            let mut voxels = make_empty_world();
+            for (x, y, z) in iter_3d(20..30, 0..4, 20..30) {
+                voxels[(x, y, z)] = if y < 2 {
+                    Voxel::new(material, 0)
+                } else {
+                    Voxel::new(Material::Acid, 0)
+                };
+            }
+            run(&mut voxels, 20);
+            200 - find(&voxels, material).len()
+        };
+        let sand = eaten(Material::Sand);
+        let stone = eaten(Material::Stone);
+        assert!(sand > 20);
+        assert!(stone < sand / 2);
+        assert_eq!(eaten(Material::Glass), 0);
+        assert_eq!(eaten(Material::Boundary), 0);
+    }
+
+    #[test]
+    fn acid_is_deterministic_for_a_seed() {
+        let simulate = |seed: u64| {
+            let mut voxels = make_empty_world();
+            for (x, y, z) in iter_3d(20..30, 0..3, 20..30) {
+                voxels[(x, y, z)] = if y == 2 {
+                    Voxel::new(Material::Acid, 0)
+                } else {
+                    wood()
+                };
+            }
+            let mut temps = make_temps(&voxels);
+            let mut awake = make_awake(&voxels);
+            let mut rng = XorShiftRng::seed_from_u64(seed);
+            for tick in 0..10 {
+                let seed = rng.gen();
+                step_voxels(
+                    &mut voxels,
+                    &mut temps,
+                    &mut Vec::new(),
+                    &mut awake,
+                    tick,
+                    seed,
+                );
+            }
+            voxels
+        };
+        assert!(simulate(7) == simulate(7));
+        assert!(simulate(7) != simulate(8));
+    }
+
+    #[test]
+    fn walls_and_wood_hold_still_in_midair() {
+        let mut voxels = make_empty_world();
+        voxels[(5, 10, 5)] = Voxel::new(Material::Wall, 0);
+        voxels[(6, 10, 5)] = wood();
+        assert!(step_sandfall(&voxels, 0).is_none());
+        // Sand poured on a floating platform piles up on it
+        for (x, y, z) in iter_3d(20..30, 10..11, 20..30) {
+            voxels[(x, y, z)] = Voxel::new(Material::Wall, 0);
+        }
+        let poured = run_with_source(&mut voxels, (25, 15, 25), sand(0), 50);
+        run(&mut voxels, 20);
+        assert_eq!(find(&voxels, Material::Wall).len(), 101);
+        assert!(find(&voxels, Material::Sand)
+            .iter()
+            .all(|&(_, y, _)| y > 10));
+        assert_eq!(find(&voxels, Material::Sand).len(), poured);
+    }
+
+    #[test]
+    fn unsupported_stone_crumbles() {
+        let mut voxels = make_empty_world();
+        voxels[(5, 10, 5)] = Voxel::new(Material::Stone, 0);
+        voxels[(8, 10, 8)] = Voxel::new(Material::Stone, 0);
+        voxels[(8, 9, 8)] = wood();
+        run(&mut voxels, 20);
+        assert_eq!(voxels[(5, 0, 5)].material, Material::Stone);
+        assert_eq!(voxels[(8, 10, 8)].material, Material::Stone);
+        // Crumbling stone falls straight down, without sliding off anything
+        voxels[(5, 1, 5)] = Voxel::new(Material::Stone, 0);
+        assert!(step_sandfall(&voxels, 0).is_none());
+    }
+
+    #[test]
+    fn ice_melts_at_room_temperature_and_near_heat() {
+        let mut voxels = make_empty_world();
+        voxels[(5, 0, 5)] = Voxel::new(Material::Ice, 0);
+        voxels[(25, 0, 25)] = Voxel::new(Material::Ice, 0);
+        voxels[(26, 0, 25)] = lava();
+        let mut temps = make_temps(&voxels);
+        run_with_temps(&mut voxels, &mut temps, 10);
+        // Ice next to lava melts right away, while ice in the open takes a while
+        assert_eq!(find(&voxels, Material::Ice), vec![(5, 0, 5)]);
+        run_with_temps(&mut voxels, &mut temps, 150);
+        assert!(find(&voxels, Material::Ice).is_empty());
+    }
+
+    #[test]
+    fn freezer_freezes_neighboring_water() {
+        let mut voxels = make_empty_world();
+        let shaft = make_shaft(&mut voxels, 5, 5, 4);
+        let column = [Voxel::new(Material::Freezer, 0), water(), water(), water()];
+        for (&(x, y, z), &voxel) in shaft.iter().zip(column.iter()) {
+            voxels[(x, y, z)] = voxel;
+        }
+        let mut temps = make_temps(&voxels);
+        run_with_temps(&mut voxels, &mut temps, 30);
+        assert_eq!(voxels[(5, 1, 5)].material, Material::Ice);
+        assert_eq!(temps[(5, 0, 5)], Material::Freezer.props().temperature);
+        // The cold spreads through the ice into the rest of the water
+        run_with_temps(&mut voxels, &mut temps, 100);
+        assert_eq!(find(&voxels, Material::Ice).len(), 3);
+    }
+
+    #[test]
+    fn spark_spreads_fire_across_oil_slick() {
+        let mut voxels = make_empty_world();
+        // A tank of water with a slick of oil on top
+        for (x, y, z) in iter_3d(19..31, 0..4, 19..31) {
+            voxels[(x, y, z)] = if x == 19 || x == 30 || z == 19 || z == 30 {
+                Voxel::BOUNDARY
+            } else if y < 2 {
+                water()
+            } else if y == 2 {
+                oil()
+            } else {
+                Voxel::AIR
+            };
+        }
+        // Light a single drop in the middle of the slick
+        voxels[(25, 2, 25)].burning = true;
+        let mut temps = make_temps(&voxels);
+        run_with_temps(&mut voxels, &mut temps, 15);
+        let unlit = find(&voxels, Material::Oil)
+            .iter()
+            .filter(|&&(x, y, z)| !voxels[(x, y, z)].burning)
+            .count();
+        assert!(unlit < 10);
+        assert_eq!(find(&voxels, Material::Water).len(), 200);
+    }
+
+    #[test]
+    fn dust_falls_slower_and_drifts_more_than_sand() {
+        // Drop a column of grains from high up, returning the highest grain and how far the grains
+        // landed from the column
+        let drop = |voxel: Voxel| {
+            let mut voxels = make_empty_world();
+            for y in 30..40 {
+                voxels[(25, y, 25)] = voxel;
+            }
+            for seed in 0..25 {
+                if let Some(next) = step_sandfall(&voxels, seed) {
+                    voxels = next;
+                }
+            }
+            let grains = find(&voxels, voxel.material);
+            let top = grains.iter().map(|&(_, y, _)| y).max().unwrap();
+            let spread: usize = grains
+                .iter()
+                .map(|&(x, _, z)| (x as i32 - 25).abs().max((z as i32 - 25).abs()) as usize)
+                .sum();
+            (top, spread)
+        };
+        let (sand_top, sand_spread) = drop(sand(0));
+        let (dust_top, dust_spread) = drop(Voxel::new(Material::Dust, 0));
+        assert!(dust_top > sand_top);
+        assert!(dust_spread > sand_spread);
+    }
+
+    #[test]
+    fn packed_dust_burns_in_a_chain_reaction() {
+        // Burn a block of dust with the given spacing between grains, returning how much dust is left
+        let burn = |spacing: usize| {
+            let mut voxels = make_empty_world();
+            for (x, y, z) in iter_3d(0..6, 1..4, 0..6) {
+                voxels[(20 + x * spacing, y * spacing, 20 + z * spacing)] =
+                    Voxel::new(Material::Dust, 0);
+            }
+            // Dust can't hold itself up, so keep it in place by only running the reactions
+            let mut temps = make_temps(&voxels);
+            voxels[(19, spacing, 20)] = fire();
+            for seed in 0..20 {
+                step_reactions(&mut voxels, &mut temps, &all_cells(), seed);
+            }
+            find(&voxels, Material::Dust).len()
+        };
+        assert_eq!(burn(1), 0);
+        assert!(burn(2) > 100);
+    }
+
+    #[test]
+    fn liquid_pressure_grows_with_depth() {
+        let mut voxels = make_empty_world();
+        let shaft = make_shaft(&mut voxels, 5, 5, 4);
+        for &(x, y, z) in shaft.iter().take(3) {
+            voxels[(x, y, z)] = water();
+        }
+        // A pipe running off the bottom of the shaft, with nothing resting on it
+        for x in 6..10 {
+            voxels[(x, 0, 5)] = water();
+            voxels[(x, 1, 5)] = Voxel::BOUNDARY;
+        }
+        let (pressures, moves) = get_pressures(&voxels, &all_cells(), 0);
+        let density = Material::Water.props().density;
+        assert_eq!(pressures[(5, 2, 5)], 0.0);
+        assert_eq!(pressures[(5, 0, 5)], 2.0 * density);
+        assert_eq!(pressures[(9, 0, 5)], 2.0 * density);
+        assert!(moves.is_empty());
+    }
+
+    #[test]
+    fn sealed_column_with_low_outlet_has_bounded_pressure() {
+        let mut voxels = make_empty_world();
+        let shaft = make_shaft(&mut voxels, 5, 5, 6);
+        for &(x, y, z) in shaft.iter() {
+            voxels[(x, y, z)] = water();
+        }
+        // An outlet off the bottom of the capped shaft, open to the air above it
+        voxels[(6, 0, 5)] = water();
+        voxels[(6, 1, 5)] = Voxel::AIR;
+        let (pressures, _) = get_pressures(&voxels, &all_cells(), 0);
+        let density = Material::Water.props().density;
+        for &(x, y, z) in shaft.iter() {
+            assert!(pressures[(x, y, z)] <= shaft.len() as f32 * density);
+        }
+        run(&mut voxels, 20);
+        assert_eq!(find(&voxels, Material::Water).len(), shaft.len() + 1);
+    }
+
+    #[test]
+    fn water_levels_out_in_u_tube() {
+        let mut voxels = make_empty_world();
+        // Two arms at x = 10 and x = 14, joined by a pipe along the bottom, with water poured into
+        // the left arm
+        for (x, y, z) in iter_3d(9..16, 0..17, 9..12) {
+            voxels[(x, y, z)] = Voxel::BOUNDARY;
+        }
+        for y in 0..16 {
+            voxels[(10, y, 10)] = if y < 13 { water() } else { Voxel::AIR };
+            voxels[(14, y, 10)] = Voxel::AIR;
+        }
+        for x in 11..14 {
+            voxels[(x, 0, 10)] = water();
+        }
+        run(&mut voxels, 50);
+        let height = |x: usize| (1..16).filter(|&y| voxels[(x, y, 10)] == water()).count();
+        assert_eq!(find(&voxels, Material::Water).len(), 16);
+        assert!(height(14) >= 5);
+        assert!((height(10) as i32 - height(14) as i32).abs() <= 1);
+    }
+
+    #[test]
+    fn deep_sand_compacts_into_sandstone() {
+        let mut voxels = make_empty_world();
+        let shaft = make_shaft(&mut voxels, 5, 5, 20);
+        for &(x, y, z) in shaft.iter() {
+            voxels[(x, y, z)] = sand(0);
+        }
+        run(&mut voxels, 1);
+        let sandstone = find(&voxels, Material::Sandstone);
+        // Sand compacts once the sand on top of it weighs enough
+        let depth = 20000.0 / Material::Sand.props().density;
+        assert_eq!(sandstone.len(), 20 - depth.ceil() as usize);
+        assert!(sandstone.iter().all(|&(_, y, _)| (y as f32) < 20.0 - depth));
+    }
+
+    #[test]
+    fn blast_throws_sand() {
+        let mut voxels = make_empty_world();
+        for (x, y, z) in iter_3d(15..36, 0..10, 15..36) {
+            voxels[(x, y, z)] = sand(0);
+        }
+        voxels[(25, 10, 25)] = lit_tnt();
+        let sand_count = find(&voxels, Material::Sand).len();
+        let mut temps = make_temps(&voxels);
+        let mut particles = Vec::new();
+        let mut awake = make_awake(&voxels);
+        let (changed, blasts) =
+            step_voxels(&mut voxels, &mut temps, &mut particles, &mut awake, 0, 0);
+        assert!(changed);
+        assert_eq!(blasts.len(), 1);
+        assert!(find(&voxels, Material::Tnt).is_empty());
+        assert!(!particles.is_empty());
+        assert_eq!(voxels[(20, 0, 20)], sand(0));
+        // Every thrown voxel lands again, some of them on top of the sand
+        for seed in 1..40 {
+            step_voxels(
+                &mut voxels,
+                &mut temps,
+                &mut particles,
+                &mut awake,
+                seed,
+                seed,
+            );
+        }
+        assert!(particles.is_empty());
+        let sand = find(&voxels, Material::Sand);
+        assert_eq!(sand.len(), sand_count);
+        assert!(sand.iter().any(|&(_, y, _)| y >= 10));
+    }
+
+    #[test]
+    fn hard_materials_shelter_from_blast() {
+        let mut voxels = make_empty_world();
+        for (_, y, z) in iter_3d(0..1, 0..20, 20..31) {
+            voxels[(23, y, z)] = wood();
+            voxels[(26, y, z)] = Voxel::new(Material::Wall, 0);
+            voxels[(27, y, z)] = wood();
+        }
+        voxels[(25, 10, 25)] = lit_tnt();
+        run(&mut voxels, 1);
+        // The wall holds, and keeps the wood behind it safe
+        assert_eq!(voxels[(26, 10, 25)].material, Material::Wall);
+        assert_eq!(voxels[(27, 10, 25)].material, Material::Wood);
+        // The wood on the open side, just as far away, is destroyed
+        assert!(voxels[(23, 10, 25)].is_air());
+    }
+
+    #[test]
+    fn blast_sets_off_nearby_explosives() {
+        let mut voxels = make_empty_world();
+        voxels[(20, 0, 20)] = lit_tnt();
+        voxels[(24, 0, 20)] = Voxel::new(Material::Tnt, 0);
+        voxels[(40, 0, 40)] = Voxel::new(Material::Tnt, 0);
+        run(&mut voxels, 2);
+        assert_eq!(find(&voxels, Material::Tnt), vec![(40, 0, 40)]);
+    }
+
+    #[test]
+    fn blast_knocks_player_away() {
+        let blast = Blast {
+            center: (10, 0, 10),
+            radius: 6.0,
+        };
+        let mut player = Player {
+            pos: Point3::new(14.0, EYE_HEIGHT, 10.5),
+            angle: Vector2::new(0.0, 0.0),
+            velocity: Vector3::new(0.0, 0.0, 0.0),
+            state: PlayerState::Normal,
+            knocked_back: false,
+        };
+        knock_back(&mut player, blast);
+        assert!(player.knocked_back);
+        assert!(player.velocity.x > 0.0);
+        assert!(player.velocity.y > 0.0);
+        // A player out of reach isn't pushed
+        let velocity = player.velocity;
+        player.pos.x = 30.0;
+        knock_back(&mut player, blast);
+        assert_eq!(player.velocity, velocity);
+    }
+
+    #[test]
+    fn blast_above_player_pushes_straight_up() {
+        let blast = Blast {
+            center: (10, 10, 10),
+            radius: 6.0,
+        };
+        // The middle of the player's body is right under the TNT
+        let mut player = Player {
+            pos: Point3::new(10.5, 7.5 + EYE_HEIGHT / 2.0, 10.5),
+            angle: Vector2::new(0.0, 0.0),
+            velocity: Vector3::new(0.0, 0.0, 0.0),
+            state: PlayerState::Normal,
+            knocked_back: false,
+        };
+        knock_back(&mut player, blast);
+        assert!(player.knocked_back);
+        assert_eq!(player.velocity.x, 0.0);
+        assert!(player.velocity.y > 0.0);
+        assert_eq!(player.velocity.z, 0.0);
+    }
+
+    #[test]
+    fn player_falls_through_gas_and_liquid() {
+        let mut voxels = make_empty_world();
+        let player = Player {
+            pos: Point3::new(10.5, 5.0 + EYE_HEIGHT, 10.5),
+            angle: Vector2::new(0.0, 0.0),
+            velocity: Vector3::new(0.0, 0.0, 0.0),
+            state: PlayerState::Normal,
+            knocked_back: false,
+        };
+        assert!(!player_is_standing(&voxels, &player));
+        for &(voxel, standing) in [(smoke(), false), (water(), false), (sand(0), true)].iter() {
+            voxels[(10, 4, 10)] = voxel;
+            assert_eq!(player_is_standing(&voxels, &player), standing);
+        }
+    }
+
+    #[test]
+    fn spring_emits_water_every_period() {
+        let mut voxels = make_empty_world();
+        voxels[(10, 5, 10)] = Voxel::new(Material::Spring, 0);
+        run(&mut voxels, 20);
+        let (period, _) = Material::Spring.props().emits.unwrap();
+        assert_eq!(find(&voxels, Material::Water).len() as u64, 20 / period);
+    }
+
+    #[test]
+    fn surrounded_emitter_emits_nothing() {
+        let mut voxels = make_empty_world();
+        let shaft = make_shaft(&mut voxels, 5, 5, 1);
+        let (x, y, z) = shaft[0];
+        voxels[(x, y, z)] = Voxel::new(Material::Spout, 0);
+        run(&mut voxels, 10);
+        assert!(find(&voxels, Material::Sand).is_empty());
+    }
+
+    #[test]
+    fn drain_swallows_falling_sand() {
+        let mut voxels = make_empty_world();
+        voxels[(10, 10, 10)] = Voxel::new(Material::Spout, 0);
+        voxels[(10, 0, 10)] = Voxel::new(Material::Drain, 0);
+        run(&mut voxels, 100);
+        // Only the sand still falling between the spout and the drain is left
+        assert!(find(&voxels, Material::Sand).len() < 10);
+        assert_eq!(voxels[(10, 0, 10)].material, Material::Drain);
+    }
+
+    // Build a sand floor for a seed to grow on at `(10, 1, 10)`, boxed in so that water poured
+    // next to the sand under the seed stays there
+    fn make_garden(voxels: &mut VoxelGrid) {
+        for (x, _, z) in iter_3d(8..13, 0..1, 8..13) {
+            voxels[(x, 0, z)] = Voxel::BOUNDARY;
+        }
+        voxels[(10, 0, 10)] = sand(0);
+        voxels[(10, 1, 10)] = Voxel::new(Material::Seed, 0);
+    }
+
+    #[test]
+    fn watered_seed_grows_stalk() {
+        let mut voxels = make_empty_world();
+        make_garden(&mut voxels);
+        for &(x, z) in [(9, 10), (11, 10), (10, 9), (10, 11)].iter() {
+            voxels[(x, 0, z)] = water();
+        }
+        run(&mut voxels, 150);
+        // Each voxel of water grows the plant once, first sprouting the seed and then growing the
+        // stalk
+        assert!(find(&voxels, Material::Water).is_empty());
+        assert!(find(&voxels, Material::Seed).is_empty());
+        assert_eq!(
+            find(&voxels, Material::Plant),
+            vec![(10, 1, 10), (10, 2, 10), (10, 3, 10), (10, 4, 10)]
+        );
+    }
+
+    #[test]
+    fn dry_seed_does_not_grow() {
+        let mut voxels = make_empty_world();
+        make_garden(&mut voxels);
+        run(&mut voxels, 100);
+        assert_eq!(find(&voxels, Material::Seed), vec![(10, 1, 10)]);
+        assert!(find(&voxels, Material::Plant).is_empty());
+    }
+
+    #[test]
+    fn buried_plant_dies() {
+        let mut voxels = make_empty_world();
+        let shaft = make_shaft(&mut voxels, 10, 10, 6);
+        for (i, &(x, y, z)) in shaft.iter().enumerate() {
+            voxels[(x, y, z)] = if i == 0 || i > 3 {
+                sand(0)
+            } else {
+                Voxel::new(Material::Plant, 0)
+            };
+        }
+        run(&mut voxels, 10);
+        assert!(find(&voxels, Material::Plant).is_empty());
+        assert_eq!(find(&voxels, Material::Soil).len(), 3);
+    }
+
+    // Lay a wire from a battery at `(10, 5, 10)` along x up to `end`, not including `end`
+    fn make_circuit(voxels: &mut VoxelGrid, end: usize) {
+        voxels[(10, 5, 10)] = Voxel::new(Material::Battery, 0);
+        for x in 11..end {
+            voxels[(x, 5, 10)] = Voxel::new(Material::Wire, 0);
+        }
+    }
+
+    #[test]
+    fn battery_sends_pulses_along_wire() {
+        let mut voxels = make_empty_world();
+        make_circuit(&mut voxels, 30);
+        let mut temps = make_temps(&voxels);
+        for _ in 0..5 {
+            step_electricity(&mut voxels, &mut temps, &all_cells());
+        }
+        // A new pulse leaves the battery every three ticks
+        let charged: Vec<VoxPos> = voxels
+            .positions()
+            .filter(|&(x, y, z)| voxels[(x, y, z)].charge == Charge::Charged)
+            .collect();
+        assert_eq!(charged, vec![(12, 5, 10), (15, 5, 10)]);
+        assert_eq!(voxels[(14, 5, 10)].charge, Charge::Discharging);
+        assert!(temps[(15, 5, 10)] > ROOM_TEMP);
+    }
+
+    #[test]
+    fn charged_wire_ignites_wood() {
+        let mut voxels = make_empty_world();
+        make_circuit(&mut voxels, 15);
+        voxels[(15, 5, 10)] = wood();
+        let mut temps = make_temps(&voxels);
+        let mut awake = make_awake(&voxels);
+        let lit = run_until(&mut voxels, &mut temps, &mut awake, 60, |voxels, _| {
+            count_burning(voxels) > 0
+        });
+        assert!(lit);
+    }
+
+    #[test]
+    fn current_boils_water() {
+        let mut voxels = make_empty_world();
+        let shaft = make_shaft(&mut voxels, 5, 5, 2);
+        let ((wx, wy, wz), (bx, by, bz)) = (shaft[0], shaft[1]);
+        voxels[(wx, wy, wz)] = water();
+        voxels[(bx, by, bz)] = Voxel::new(Material::Battery, 0);
+        let mut temps = make_temps(&voxels);
+        let mut awake = make_awake(&voxels);
+        let boiled = run_until(&mut voxels, &mut temps, &mut awake, 150, |voxels, _| {
+            voxels[(wx, wy, wz)].material == Material::Steam
+        });
+        assert!(boiled);
+    }
+
+    // Count the chunks that are awake
+    fn count_awake(awake: &AwakeSet) -> usize {
+        awake.len()
+    }
+
+    // A pile of sand dropped next to a pool of water, spread over several chunks
+    fn make_sand_and_water(voxels: &mut VoxelGrid) {
+        for (x, y, z) in iter_3d(8..13, 15..20, 8..13) {
+            voxels[(x, y, z)] = sand((x + z) as VoxelShade);
+        }
+        for (x, y, z) in iter_3d(22..26, 5..8, 8..12) {
+            voxels[(x, y, z)] = water();
+        }
+    }
+
+    #[test]
+    fn settled_world_falls_asleep() {
+        let mut voxels = make_empty_world();
+        make_sand_and_water(&mut voxels);
+        let mut temps = make_temps(&voxels);
+        let mut awake = make_awake(&voxels);
+        let asleep = run_until(&mut voxels, &mut temps, &mut awake, 300, |_, awake| {
+            count_awake(awake) == 0
+        });
+        assert!(asleep);
+        assert_eq!(find(&voxels, Material::Sand).len(), 125);
+        assert_eq!(find(&voxels, Material::Water).len(), 48);
+    }
+
+    // Run a pile of sand falling into water for a while, drawing the seed of each tick from an RNG
+    // started at `seed` like the game does, and hash the resulting world
+    fn run_seeded(seed: u64) -> u64 {
+        let mut rng = XorShiftRng::seed_from_u64(seed);
+        let mut voxels = make_empty_world();
+        make_sand_and_water(&mut voxels);
+        let mut temps = make_temps(&voxels);
+        let mut awake = make_awake(&voxels);
+        for tick in 0..40 {
+            step_voxels(
+                &mut voxels,
+                &mut temps,
+                &mut Vec::new(),
+                &mut awake,
+                tick,
+                rng.gen(),
+            );
+        }
+        voxels.state_hash()
+    }
+
+    #[test]
+    fn seed_decides_world() {
+        assert_eq!(run_seeded(7), run_seeded(7));
+        assert_ne!(run_seeded(7), run_seeded(8));
+    }
+
+    #[test]
+    fn far_chunks_are_frozen() {
+        let mut voxels = client::make_empty_world((100, 20, 20));
+        voxels[(5, 15, 5)] = sand(0);
+        voxels[(95, 15, 5)] = sand(0);
+        let mut temps = make_temps(&voxels);
+        let mut awake = make_awake(&voxels);
+        let mut run = |voxels: &mut VoxelGrid, temps: &mut TempGrid| {
+            for seed in 0..30 {
+                step_voxels(voxels, temps, &mut Vec::new(), &mut awake, seed, seed);
+            }
+        };
+        load_chunks_around(&mut voxels, &mut temps, (0, 1, 0));
+        assert!(!voxels.is_loaded((9, 1, 0)));
+        assert!(voxels.is_blank((5, 0, 0)));
+        run(&mut voxels, &mut temps);
+        assert_eq!(find(&voxels, Material::Sand), vec![(5, 0, 5), (95, 15, 5)]);
+        load_chunks_around(&mut voxels, &mut temps, (9, 1, 0));
+        assert!(!voxels.is_loaded((0, 1, 0)));
+        run(&mut voxels, &mut temps);
+        assert_eq!(find(&voxels, Material::Sand), vec![(5, 0, 5), (95, 0, 5)]);
+    }
+
+    #[test]
+    fn world_size_need_not_fit_whole_chunks() {
+        let mut voxels = client::make_empty_world((27, 23, 14));
+        make_sand_and_water(&mut voxels);
+        let mut temps = make_temps(&voxels);
+        let mut awake = make_awake(&voxels);
+        assert_eq!(voxels.chunk_counts(), (3, 3, 2));
+        let asleep = run_until(&mut voxels, &mut temps, &mut awake, 300, |_, awake| {
+            count_awake(awake) == 0
+        });
+        assert!(asleep);
+        assert_eq!(find(&voxels, Material::Sand).len(), 125);
+        assert_eq!(find(&voxels, Material::Water).len(), 48);
+        assert!(find(&voxels, Material::Sand).iter().all(|&(_, y, _)| y < 5));
+    }
+
+    #[test]
+    fn sleeping_chunks_match_full_simulation() {
+        let simulate = |track: bool| {
+            let mut voxels = make_empty_world();
+            make_sand_and_water(&mut voxels);
+            let mut temps = make_temps(&voxels);
+            let mut awake = make_awake(&voxels);
+            for seed in 0..60 {
+                if !track {
+                    awake = make_awake(&voxels);
+                }
+                step_voxels(
+                    &mut voxels,
+                    &mut temps,
+                    &mut Vec::new(),
+                    &mut awake,
+                    seed,
+                    seed,
+                );
+            }
+            voxels
+        };
+        assert!(simulate(true) == simulate(false));
+    }
+
+    #[test]
+    fn result_does_not_depend_on_thread_count() {
+        let simulate = |threads: usize| {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap();
+            pool.install(|| {
+                let mut voxels = make_empty_world();
+                make_sand_and_water(&mut voxels);
+                voxels[(30, 5, 30)] = lava();
+                voxels[(30, 4, 30)] = wood();
+                run(&mut voxels, 40);
+                voxels
+            })
+        };
+        assert!(simulate(1) == simulate(4));
+    }
+
+    #[test]
+    fn voxels_with_nothing_to_react_with_fall_asleep() {
+        let materials = [
+            Material::Seed,
+            Material::Plant,
+            Material::Lava,
+            Material::Drain,
+            Material::Acid,
+        ];
+        for &material in materials.iter() {
+            let mut voxels = make_empty_world();
+            let shaft = make_shaft(&mut voxels, 5, 5, 1);
+            let (x, y, z) = shaft[0];
+            voxels[(x, y, z)] = Voxel::new(material, 0);
+            let mut temps = make_temps(&voxels);
+            let mut awake = make_awake(&voxels);
+            let asleep = run_until(&mut voxels, &mut temps, &mut awake, 200, |_, awake| {
+                count_awake(awake) == 0
+            });
+            assert!(asleep, "{:?} kept its chunk awake", material);
+            assert_eq!(voxels[(x, y, z)].material, material);
+        }
+    }
+
+    #[test]
+    fn disturbed_pool_wakes_only_nearby_chunks() {
+        let mut voxels = make_empty_world();
+        for (x, y, z) in iter_3d(0..50, 0..1, 0..50) {
+            voxels[(x, y, z)] = water();
+        }
+        let mut temps = make_temps(&voxels);
+        let mut awake = make_awake(&voxels);
+        let asleep = run_until(&mut voxels, &mut temps, &mut awake, 50, |_, awake| {
+            count_awake(awake) == 0
+        });
+        assert!(asleep);
+        // Drop a grain of sand into one corner of the pool. Only the chunks around it should be
+        // simulated, not the whole pool.
+        voxels[(1, 1, 1)] = sand(0);
+        wake_around(&mut awake, voxels.size(), (1, 1, 1), true);
+        run_until(&mut voxels, &mut temps, &mut awake, 20, |voxels, awake| {
+            let mut active = awake.clone();
+            wake_liquid_bodies(voxels, &mut active);
+            assert!(count_awake(&active) <= 4);
+            false
+        });
+    }
+
+    #[test]
+    fn change_wakes_sleeping_chunks() {
+        let mut voxels = make_empty_world();
+        for (x, y, z) in iter_3d(4..7, 25..26, 4..7) {
+            voxels[(x, y, z)] = Voxel::new(Material::Wall, 0);
+        }
+        voxels[(5, 26, 5)] = sand(0);
+        let mut temps = make_temps(&voxels);
+        let mut awake = make_awake(&voxels);
+        step_voxels(&mut voxels, &mut temps, &mut Vec::new(), &mut awake, 0, 0);
+        assert_eq!(count_awake(&awake), 0);
+        // Knock the wall out from under the grain, like the player does
+        voxels[(5, 25, 5)] = Voxel::AIR;
+        wake_around(&mut awake, voxels.size(), (5, 25, 5), true);
+        for seed in 1..40 {
+            step_voxels(
+                &mut voxels,
+                &mut temps,
+                &mut Vec::new(),
+                &mut awake,
+                seed,
+                seed,
+            );
+        }
+        assert_eq!(voxels[(5, 0, 5)], sand(0));
+    }
+
+    fn count_due_ticks(speed: usize, steps: u32) -> u32 {
+        let mut progress = 0;
+        (0..steps)
+            .map(|_| get_due_ticks(&mut progress, speed))
+            .sum()
+    }
+
+    #[test]
+    fn sim_speed_scales_tick_rate() {
+        let steps = 8 * STEPS_PER_TICK;
+        assert_eq!(count_due_ticks(NORMAL_SPEED, steps), 8);
+        assert_eq!(count_due_ticks(0, steps), 2);
+        assert_eq!(count_due_ticks(SIM_SPEEDS.len() - 1, steps), 64);
     }
 }
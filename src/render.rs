@@ -16,15 +16,10 @@ use clamp::clamp;
 
 use image::RgbaImage;
 
-use nd_iter::iter_3d;
-
-use client::{
-    GameState, Graphics, PlayerState, SightBlock, Voxel, VoxelShade, VOX_MAX_X, VOX_MAX_Y,
-    VOX_MAX_Z,
-};
+use client::{Charge, GameState, Graphics, PlayerState, SightBlock, Voxel, VoxelShade};
 use {client, physics};
 
-pub type VoxInd = i8;
+pub type VoxInd = i16; // Wide enough for worlds several hundred voxels across
 
 implement_vertex!(VoxelVertex, pos, color);
 #[derive(Clone, Copy)]
@@ -75,6 +70,8 @@ impl SkyboxVertex {
 }
 
 const SHADE_VARIATION: f32 = 0.1;
+const BURNING_TINT: [f32; 3] = [1.0, 0.3, 0.0];
+const CHARGED_TINT: [f32; 3] = [1.0, 1.0, 0.5];
 const NORMAL_FOV: Deg<f32> = Deg(60.0);
 const RUNNING_FOV: Deg<f32> = Deg(70.0);
 const FOV_CHANGE_TIME: f32 = 0.06; // The time required to change between `NORMAL_FOV` and `RUNNING_FOV` in seconds
@@ -171,33 +168,52 @@ fn expand_shade(shade: VoxelShade) -> [f32; 3] {
     ]
 }
 
-// Get the color of a voxel variant
+// Mix a color halfway with a tint
+fn tint_color([r, g, b, a]: [f32; 4], [tr, tg, tb]: [f32; 3]) -> [f32; 4] {
+    [(r + tr) / 2.0, (g + tg) / 2.0, (b + tb) / 2.0, a]
+}
+
+// Get the color of a voxel from its material's base color and its shade. Burning voxels are
+// tinted with the color of fire, and charged voxels with the color of a spark.
 fn get_voxel_color(voxel: Voxel) -> [f32; 4] {
-    match voxel {
-        Voxel::Air => [1.0, 1.0, 1.0, 0.0],
-        Voxel::Boundary => [0.0, 0.0, 0.0, 1.0],
-        Voxel::Sand(shade) => {
-            let [x, y, z] = expand_shade(shade);
-            [0.93 + x, 0.79 + y, 0.49 + z, 1.0]
-        }
+    let [r, g, b, a] = voxel.props().color;
+    let [x, y, z] = expand_shade(voxel.shade);
+    let color = [r + x, g + y, b + z, a];
+    if voxel.burning {
+        tint_color(color, BURNING_TINT)
+    } else if voxel.charge == Charge::Charged {
+        tint_color(color, CHARGED_TINT)
+    } else {
+        color
     }
 }
 
-// Make a mesh of the voxel world
+// Add a cube with its corner at `pos` to a voxel mesh
+fn add_cube(mesh: &mut Vec<VoxelVertex>, [x, y, z]: [VoxInd; 3], color: [f32; 4]) {
+    for [vx, vy, vz] in CUBE_VERTICES.iter() {
+        mesh.push(VoxelVertex::new([vx + x, vy + y, vz + z], color));
+    }
+}
+
+// Make a mesh of the voxel world. Flying particles are drawn as voxels in the cells they're
+// passing through.
 fn make_voxels_mesh(state: &GameState) -> Vec<VoxelVertex> {
     let mut mesh = Vec::new();
-    // Iterate through all the voxels, creating a cube mesh for each
-    for (x, y, z) in iter_3d(0..VOX_MAX_X, 0..VOX_MAX_Y, 0..VOX_MAX_Z) {
-        let voxel = state.voxels[x][y][z];
-        if !voxel.is_air() {
-            for [vx, vy, vz] in CUBE_VERTICES.iter() {
-                mesh.push(VoxelVertex::new(
-                    [vx + x as VoxInd, vy + y as VoxInd, vz + z as VoxInd],
-                    get_voxel_color(voxel),
-                ));
+    // Iterate through the voxels in the chunks that have been written to, creating a cube mesh for
+    // each. Unloaded chunks are drawn from their packed runs, so they stay in view.
+    for chunk in state.voxels.stored_chunks() {
+        for ((x, y, z), voxel) in state.voxels.chunk_values(chunk) {
+            if !voxel.is_air() {
+                let pos = [x as VoxInd, y as VoxInd, z as VoxInd];
+                add_cube(&mut mesh, pos, get_voxel_color(*voxel));
             }
         }
     }
+    for particle in state.particles.iter() {
+        let Point3 { x, y, z } = particle.pos;
+        let pos = [x as VoxInd, y as VoxInd, z as VoxInd];
+        add_cube(&mut mesh, pos, get_voxel_color(particle.voxel));
+    }
     mesh
 }
 
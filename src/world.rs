@@ -0,0 +1,443 @@
+use nd_iter::iter_3d;
+
+use std::collections::BTreeSet;
+use std::hash::Hasher;
+use std::iter;
+use std::ops::{Index, IndexMut};
+
+// Position of a cell in a grid
+pub type VoxPos = (usize, usize, usize);
+
+pub const DEFAULT_WORLD_SIZE: VoxPos = (50, 50, 50); // Size of the voxel grid unless told otherwise
+pub const CHUNK_SIZE: usize = 10; // Cells along each side of a chunk
+pub const CHUNK_VOLUME: usize = CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE; // Cells in a chunk
+
+// Storage for one chunk of a grid that has been written to
+#[derive(Clone, Debug)]
+enum Chunk<T> {
+    Loaded(Box<[T]>),          // Every cell stored
+    Unloaded(Vec<(T, usize)>), // Packed away as runs of equal cells
+}
+
+// A box-shaped grid of values, such as one for each voxel in the world. The grid is split into
+// cubic chunks, and a chunk is only stored once something other than the fill value is written to
+// it, so a large world that's mostly air takes little memory. The chunk table has an entry for
+// every chunk, stored or not, so finding a cell's chunk is a single lookup. Chunks can be
+// unloaded, which packs them into runs of equal cells. Writing to an unloaded chunk writes into its
+// runs, so it stays unloaded. Everything that needs the size of the world asks the grid for it.
+#[derive(Clone, Debug)]
+pub struct Grid<T> {
+    size: VoxPos,
+    chunk_counts: VoxPos,
+    fill: T,
+    chunks: Vec<Option<Chunk<T>>>,   // Stored chunks, by index
+    loaded: Option<BTreeSet<usize>>, // Indices of the loaded chunks, or `None` if all are loaded
+}
+
+impl<T: Clone> Grid<T> {
+    // Create a grid of `size` cells along each axis, all set to `value`. Every chunk starts loaded.
+    pub fn new(size: VoxPos, value: T) -> Self {
+        let (sx, sy, sz) = size;
+        let chunk_counts = (
+            sx.div_ceil(CHUNK_SIZE),
+            sy.div_ceil(CHUNK_SIZE),
+            sz.div_ceil(CHUNK_SIZE),
+        );
+        let (cx, cy, cz) = chunk_counts;
+        Grid {
+            size,
+            chunk_counts,
+            fill: value,
+            chunks: vec![None; cx * cy * cz],
+            loaded: None,
+        }
+    }
+}
+
+impl<T: Clone + PartialEq> Grid<T> {
+    // Load the chunks in `chunks` so that their cells can be simulated, and unload every other
+    // chunk. Only the stored chunks need to be unpacked or packed, and a chunk that holds nothing
+    // but the fill value stops being stored when it's unloaded.
+    pub fn load_only<I: IntoIterator<Item = VoxPos>>(&mut self, chunks: I) {
+        let loaded: BTreeSet<usize> = chunks
+            .into_iter()
+            .map(|chunk| self.chunk_index(chunk))
+            .collect();
+        let fill = &self.fill;
+        for (i, entry) in self.chunks.iter_mut().enumerate() {
+            let chunk = match *entry {
+                Some(ref mut chunk) => chunk,
+                None => continue,
+            };
+            *chunk = match *chunk {
+                Chunk::Unloaded(ref runs) if loaded.contains(&i) => {
+                    let cells: Vec<T> = runs
+                        .iter()
+                        .flat_map(|&(ref value, len)| vec![value.clone(); len])
+                        .collect();
+                    Chunk::Loaded(cells.into_boxed_slice())
+                }
+                Chunk::Loaded(ref cells) if !loaded.contains(&i) => {
+                    if cells.iter().all(|cell| cell == fill) {
+                        *entry = None;
+                        continue;
+                    }
+                    let mut runs: Vec<(T, usize)> = Vec::new();
+                    for cell in cells.iter() {
+                        match runs.last_mut() {
+                            Some(&mut (ref value, ref mut len)) if value == cell => *len += 1,
+                            _ => runs.push((cell.clone(), 1)),
+                        }
+                    }
+                    Chunk::Unloaded(runs)
+                }
+                _ => continue,
+            };
+        }
+        self.loaded = Some(loaded);
+    }
+}
+
+impl<T: StateHash + PartialEq> Grid<T> {
+    // Hash the cells of the grid, such as to check that two runs of the simulation ended up with
+    // the same world. The hash depends on the size and the cells, but not on how the chunks are
+    // stored or on the machine it's computed on.
+    pub fn state_hash(&self) -> u64 {
+        let mut hasher = FnvHasher(FNV_OFFSET_BASIS);
+        let (x, y, z) = self.size;
+        for &len in [x, y, z].iter() {
+            hasher.write(&(len as u64).to_le_bytes());
+        }
+        self.fill.state_hash(&mut hasher);
+        for chunk in self.chunk_positions() {
+            // Most chunks hold nothing but the fill value, so each of those is hashed as a marker
+            let blank = self.is_blank(chunk)
+                || self.chunk_cells(chunk, 0).all(|pos| self[pos] == self.fill);
+            hasher.write(&[blank as u8]);
+            if !blank {
+                for pos in self.chunk_cells(chunk, 0) {
+                    self[pos].state_hash(&mut hasher);
+                }
+            }
+        }
+        hasher.finish()
+    }
+}
+
+impl<T> Grid<T> {
+    // Get the number of cells along each axis
+    pub fn size(&self) -> VoxPos {
+        self.size
+    }
+
+    // Get the number of chunks along each axis. Chunks at the far edges are cut short when the size
+    // isn't a multiple of the chunk size.
+    pub fn chunk_counts(&self) -> VoxPos {
+        self.chunk_counts
+    }
+
+    // Determine if a position is inside the grid
+    pub fn contains(&self, (x, y, z): VoxPos) -> bool {
+        let (sx, sy, sz) = self.size;
+        x < sx && y < sy && z < sz
+    }
+
+    // Offset a position, returning `None` if the result is outside the grid
+    pub fn offset(&self, (x, y, z): VoxPos, (dx, dy, dz): (i32, i32, i32)) -> Option<VoxPos> {
+        let pos = (
+            (x as i64 + dx as i64) as usize,
+            (y as i64 + dy as i64) as usize,
+            (z as i64 + dz as i64) as usize,
+        );
+        Some(pos).filter(|&pos| self.contains(pos))
+    }
+
+    // Get the cell at a position, returning `None` if it's outside the grid
+    pub fn get(&self, pos: VoxPos) -> Option<&T> {
+        if self.contains(pos) {
+            Some(&self[pos])
+        } else {
+            None
+        }
+    }
+
+    // Get every position in the grid
+    pub fn positions(&self) -> impl Iterator<Item = VoxPos> {
+        let (sx, sy, sz) = self.size;
+        iter_3d(0..sx, 0..sy, 0..sz)
+    }
+
+    // Get the position of every chunk in the grid
+    pub fn chunk_positions(&self) -> impl Iterator<Item = VoxPos> {
+        let (cx, cy, cz) = self.chunk_counts;
+        iter_3d(0..cx, 0..cy, 0..cz)
+    }
+
+    // Get the positions of the cells in a chunk, along with the cells up to `margin` away from it
+    pub fn chunk_cells(&self, (cx, cy, cz): VoxPos, margin: usize) -> impl Iterator<Item = VoxPos> {
+        let range = move |c: usize, max: usize| {
+            (c * CHUNK_SIZE).saturating_sub(margin)..((c + 1) * CHUNK_SIZE + margin).min(max)
+        };
+        let (sx, sy, sz) = self.size;
+        iter_3d(range(cx, sx), range(cy, sy), range(cz, sz))
+    }
+
+    // Get the position of every chunk that's stored, in order
+    pub fn stored_chunks(&self) -> impl Iterator<Item = VoxPos> + '_ {
+        let (_, cy, cz) = self.chunk_counts;
+        self.chunks
+            .iter()
+            .enumerate()
+            .filter(|&(_, chunk)| chunk.is_some())
+            .map(move |(i, _)| (i / (cy * cz), i / cz % cy, i % cz))
+    }
+
+    // Get the positions and cells of a chunk, in order. An unloaded chunk's runs are read one after
+    // another, which is quicker than looking up each of its cells.
+    pub fn chunk_values(&self, chunk: VoxPos) -> impl Iterator<Item = (VoxPos, &T)> {
+        let runs: Vec<(&T, usize)> = match &self.chunks[self.chunk_index(chunk)] {
+            None => vec![(&self.fill, CHUNK_VOLUME)],
+            Some(Chunk::Loaded(cells)) => cells.iter().map(|cell| (cell, 1)).collect(),
+            Some(Chunk::Unloaded(runs)) => {
+                runs.iter().map(|&(ref value, len)| (value, len)).collect()
+            }
+        };
+        let (cx, cy, cz) = chunk;
+        let (sx, sy, sz) = self.size;
+        iter_3d(0..CHUNK_SIZE, 0..CHUNK_SIZE, 0..CHUNK_SIZE)
+            .map(move |(x, y, z)| {
+                (
+                    cx * CHUNK_SIZE + x,
+                    cy * CHUNK_SIZE + y,
+                    cz * CHUNK_SIZE + z,
+                )
+            })
+            .zip(
+                runs.into_iter()
+                    .flat_map(|(value, len)| iter::repeat_n(value, len)),
+            )
+            .filter(move |&((x, y, z), _)| x < sx && y < sy && z < sz)
+    }
+
+    // Determine if a chunk is loaded
+    pub fn is_loaded(&self, chunk: VoxPos) -> bool {
+        let i = self.chunk_index(chunk);
+        self.loaded
+            .as_ref()
+            .is_none_or(|loaded| loaded.contains(&i))
+    }
+
+    // Determine if a chunk is known to hold nothing but the fill value without looking at its
+    // cells, which is the case when it isn't stored
+    pub fn is_blank(&self, chunk: VoxPos) -> bool {
+        self.chunks[self.chunk_index(chunk)].is_none()
+    }
+
+    fn chunk_index(&self, (x, y, z): VoxPos) -> usize {
+        let (_, cy, cz) = self.chunk_counts;
+        (x * cy + y) * cz + z
+    }
+
+    // Get the index of the chunk containing a position, and the index of the cell within it
+    fn locate(&self, (x, y, z): VoxPos) -> (usize, usize) {
+        assert!(
+            self.contains((x, y, z)),
+            "{:?} is outside the grid",
+            (x, y, z)
+        );
+        let chunk = (x / CHUNK_SIZE, y / CHUNK_SIZE, z / CHUNK_SIZE);
+        let (lx, ly, lz) = (x % CHUNK_SIZE, y % CHUNK_SIZE, z % CHUNK_SIZE);
+        (
+            self.chunk_index(chunk),
+            (lx * CHUNK_SIZE + ly) * CHUNK_SIZE + lz,
+        )
+    }
+}
+
+// Cell values that can be fed to Grid::state_hash. Unlike Hash, which leaves the bytes up to the
+// platform and compiler, implementations write a fixed layout so the hash is the same everywhere.
+pub trait StateHash {
+    fn state_hash<H: Hasher>(&self, hasher: &mut H);
+}
+
+impl StateHash for u8 {
+    fn state_hash<H: Hasher>(&self, hasher: &mut H) {
+        hasher.write(&[*self]);
+    }
+}
+
+// FNV-1a hasher. Unlike the standard library's hasher, its output is specified, so hashes can be
+// compared between builds.
+struct FnvHasher(u64);
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x100_0000_01b3;
+
+impl Hasher for FnvHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(FNV_PRIME);
+        }
+    }
+}
+
+// Grids are equal when they hold the same cells, however their chunks are stored
+impl<T: PartialEq> PartialEq for Grid<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.size == other.size && self.positions().all(|pos| self[pos] == other[pos])
+    }
+}
+
+impl<T> Index<VoxPos> for Grid<T> {
+    type Output = T;
+
+    fn index(&self, pos: VoxPos) -> &T {
+        let (i, j) = self.locate(pos);
+        match &self.chunks[i] {
+            None => &self.fill,
+            Some(Chunk::Loaded(cells)) => &cells[j],
+            Some(Chunk::Unloaded(runs)) => {
+                let mut end = 0;
+                for &(ref value, len) in runs.iter() {
+                    end += len;
+                    if j < end {
+                        return value;
+                    }
+                }
+                &self.fill
+            }
+        }
+    }
+}
+
+// Split the runs of an unloaded chunk so that the cell at index `j` is a run of its own, and get
+// the value of that run
+fn split_run<T: Clone>(runs: &mut Vec<(T, usize)>, j: usize) -> &mut T {
+    let mut start = 0;
+    let mut k = 0;
+    while j >= start + runs[k].1 {
+        start += runs[k].1;
+        k += 1;
+    }
+    let (value, len) = runs[k].clone();
+    let (before, after) = (j - start, start + len - j - 1);
+    let pieces = vec![(value.clone(), before), (value.clone(), 1), (value, after)];
+    runs.splice(k..k + 1, pieces.into_iter().filter(|&(_, len)| len > 0));
+    &mut runs[k + (before > 0) as usize].0
+}
+
+// Writing to a cell stores its chunk, packed into runs if the chunk is unloaded
+impl<T: Clone> IndexMut<VoxPos> for Grid<T> {
+    fn index_mut(&mut self, pos: VoxPos) -> &mut T {
+        let (i, j) = self.locate(pos);
+        if self.chunks[i].is_none() {
+            let chunk = if self
+                .loaded
+                .as_ref()
+                .is_none_or(|loaded| loaded.contains(&i))
+            {
+                Chunk::Loaded(vec![self.fill.clone(); CHUNK_VOLUME].into())
+            } else {
+                Chunk::Unloaded(vec![(self.fill.clone(), CHUNK_VOLUME)])
+            };
+            self.chunks[i] = Some(chunk);
+        }
+        match &mut self.chunks[i] {
+            Some(Chunk::Loaded(cells)) => &mut cells[j],
+            Some(Chunk::Unloaded(runs)) => split_run(runs, j),
+            None => unreachable!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unloading_keeps_cells() {
+        let mut grid = Grid::new((25, 12, 30), 0);
+        for (i, pos) in grid.chunk_cells((1, 0, 2), 0).enumerate() {
+            grid[pos] = i / 300;
+        }
+        grid[(3, 11, 29)] = 7;
+        let before = grid.clone();
+        grid.load_only(Vec::new());
+        for chunk in grid.chunk_positions() {
+            assert!(!grid.is_loaded(chunk));
+        }
+        assert!(grid == before);
+        assert!(grid.is_blank((0, 0, 0)));
+        assert!(!grid.is_blank((1, 0, 2)));
+        grid.load_only(vec![(1, 0, 2)]);
+        assert!(grid.is_loaded((1, 0, 2)));
+        assert!(!grid.is_loaded((0, 1, 2)));
+        assert!(grid == before);
+    }
+
+    #[test]
+    fn hash_ignores_chunk_storage() {
+        let mut grid = Grid::new((30, 30, 30), 0u8);
+        grid[(4, 5, 6)] = 3;
+        grid[(25, 0, 0)] = 0;
+        let hash = grid.state_hash();
+        grid.load_only(vec![(1, 1, 1)]);
+        assert_eq!(grid.state_hash(), hash);
+        grid[(4, 5, 7)] = 3;
+        assert_ne!(grid.state_hash(), hash);
+    }
+
+    #[test]
+    fn hash_has_fixed_layout() {
+        let mut grid = Grid::new((12, 1, 1), 0u8);
+        grid[(2, 0, 0)] = 3;
+        assert_eq!(grid.state_hash(), 0x0d65_e3e3_8748_35c1);
+    }
+
+    #[test]
+    fn writing_keeps_chunk_unloaded() {
+        let mut grid = Grid::new((20, 20, 20), 0);
+        grid.load_only(Vec::new());
+        grid[(15, 15, 5)] = 1;
+        grid[(15, 15, 7)] = 2;
+        grid[(15, 15, 6)] = 3;
+        assert!(!grid.is_loaded((1, 1, 0)));
+        assert!(!grid.is_blank((1, 1, 0)));
+        let cells = [0, 1, 3, 2, 0];
+        for (z, &cell) in cells.iter().enumerate() {
+            assert_eq!(grid[(15, 15, z + 4)], cell);
+        }
+        grid.load_only(vec![(1, 1, 0)]);
+        for (z, &cell) in cells.iter().enumerate() {
+            assert_eq!(grid[(15, 15, z + 4)], cell);
+        }
+    }
+
+    #[test]
+    fn chunk_values_match_cells() {
+        let mut grid = Grid::new((25, 12, 30), 0);
+        grid[(3, 4, 5)] = 1;
+        grid[(24, 11, 29)] = 2;
+        grid.load_only(vec![(0, 0, 0)]);
+        grid[(22, 10, 21)] = 3;
+        assert_eq!(
+            grid.stored_chunks().collect::<Vec<_>>(),
+            vec![(0, 0, 0), (2, 1, 2)]
+        );
+        for chunk in grid.chunk_positions() {
+            let values: Vec<(VoxPos, usize)> = grid
+                .chunk_values(chunk)
+                .map(|(pos, &value)| (pos, value))
+                .collect();
+            let cells: Vec<(VoxPos, usize)> = grid
+                .chunk_cells(chunk, 0)
+                .map(|pos| (pos, grid[pos]))
+                .collect();
+            assert_eq!(values, cells);
+        }
+    }
+}